@@ -0,0 +1,10 @@
+use cfg_aliases::cfg_aliases;
+
+fn main() {
+    // `egl`: the platforms glutin uses EGL on, where --screenshot can render without a display.
+    // Cargo.toml can't use the alias, its glutin_egl_sys and libloading dependencies list the
+    // same platforms.
+    cfg_aliases! {
+        egl: { any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd") },
+    }
+}
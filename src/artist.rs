@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+use mpd::song::Song;
+use log::{info, warn};
+use crate::config::{ArtistConfig, LookupSource};
+use crate::paths;
+
+// Characters that can't (or shouldn't) be part of a file name
+const UNSAFE_CHARS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+// Turns the artist tag of a song into the background image names to look for
+pub struct ArtistMatcher {
+    lookup_order: Vec<LookupSource>,
+    separators: Vec<String>,
+    featuring: Vec<String>,
+    // normalized variant spelling -> image name
+    aliases: HashMap<String, String>,
+}
+
+impl ArtistMatcher {
+    pub fn new(config: &ArtistConfig) -> ArtistMatcher {
+        ArtistMatcher {
+            lookup_order: config.lookup_order.clone(),
+            separators: config.separators.iter().map(|sep| sep.to_lowercase()).collect(),
+            featuring: config.featuring.iter().map(|marker| marker.to_lowercase()).collect(),
+            aliases: load_all_aliases(),
+        }
+    }
+
+    // Image names to try for a song, going through the tags in the configured lookup order
+    pub fn song_candidates(&self, song: &Song) -> Vec<String> {
+        let mut candidates: Vec<String> = Vec::new();
+        for source in &self.lookup_order {
+            match source {
+                LookupSource::Musicbrainz => {
+                    let ids = song.tags.iter()
+                        .filter(|(key, _)| key == "MUSICBRAINZ_ARTISTID")
+                        .flat_map(|(_, value)| value.split([';', '/', ',']))
+                        .map(|id| id.trim().to_lowercase());
+                    candidates.extend(ids);
+                },
+                LookupSource::AlbumArtist => {
+                    for (_, album_artist) in song.tags.iter().filter(|(key, _)| key == "AlbumArtist") {
+                        candidates.extend(self.candidates(album_artist));
+                    }
+                },
+                LookupSource::Artist => {
+                    if let Some(artist) = &song.artist {
+                        candidates.extend(self.candidates(artist));
+                    }
+                },
+            }
+        }
+        dedup(candidates)
+    }
+
+    // Image names to try for an artist tag, best match first.
+    // The whole tag is tried before each artist in it, so duos and names like "AC/DC" can
+    // have their own image, and before the featured artists are cut off, so a band like
+    // "Little Feat" still finds its own. For each name an alias wins, then the plain lowercase
+    // name (the format older versions used), then the normalized name.
+    pub fn candidates(&self, artist: &str) -> Vec<String> {
+        let lowered = artist.trim().to_lowercase();
+        let mut names = vec![lowered.clone()];
+        for without_featuring in self.strip_featuring(&lowered) {
+            names.push(without_featuring.to_owned());
+            names.extend(self.split(without_featuring));
+        }
+
+        let mut candidates: Vec<String> = Vec::new();
+        for name in names {
+            let normalized = normalize(&name);
+            if let Some(alias) = self.aliases.get(&normalized) {
+                candidates.push(alias.clone());
+            }
+            if !name.contains(UNSAFE_CHARS) {
+                candidates.push(name);
+            }
+            candidates.push(normalized);
+        }
+        dedup(candidates)
+    }
+
+    // Cuts "feat. X", "(ft. X)" etc. off the end of a lowercase artist tag. A marker only counts
+    // when another artist follows it, so "little feat" is left alone. Every place the tag could
+    // be cut is returned, longest first, or the whole tag if there's none, so "little feat feat. bonnie raitt" still tries
+    // "little feat" before "little".
+    fn strip_featuring<'a>(&self, artist: &'a str) -> Vec<&'a str> {
+        let mut ends = Vec::new();
+        for marker in &self.featuring {
+            for (start, _) in artist.match_indices(marker.as_str()) {
+                let after = start + marker.len();
+                let starts_word = artist[..start].ends_with([' ', '(', '[']);
+                let followed_by_artist = artist[after..].starts_with(' ') && !artist[after..].trim().is_empty();
+                if start > 0 && starts_word && followed_by_artist {
+                    ends.push(start);
+                }
+            }
+        }
+        if ends.is_empty() {
+            ends.push(artist.len());
+        }
+        ends.sort_unstable_by(|a, b| b.cmp(a));
+        ends.dedup();
+        ends.iter()
+            .map(|end| artist[..*end].trim_end_matches([' ', '(', '[', ',']).trim())
+            .collect()
+    }
+
+    // Splits a lowercase artist tag into the individual artists
+    fn split(&self, artist: &str) -> Vec<String> {
+        let mut parts = vec![artist.to_owned()];
+        for separator in &self.separators {
+            parts = parts.iter()
+                .flat_map(|part| part.split(separator.as_str()).map(|s| s.to_owned()).collect::<Vec<String>>())
+                .collect();
+        }
+        parts.iter()
+            .map(|part| part.trim().to_owned())
+            .filter(|part| !part.is_empty())
+            .collect()
+    }
+}
+
+// Drops empty and repeated names, keeping the first occurrence
+fn dedup(candidates: Vec<String>) -> Vec<String> {
+    let mut deduped: Vec<String> = Vec::new();
+    for candidate in candidates {
+        if !candidate.is_empty() && !deduped.contains(&candidate) {
+            deduped.push(candidate);
+        }
+    }
+    deduped
+}
+
+// Folds an artist name into a filesystem-safe form: diacritics and compatibility characters are
+// folded (NFKD), unsafe characters removed, whitespace collapsed and trailing periods dropped.
+// "Beyoncé" -> "beyonce", "AC/DC" -> "acdc", "Sunn O)))" stays "sunn o)))".
+pub fn normalize(name: &str) -> String {
+    let folded: String = name.nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .filter(|c| !c.is_control() && !UNSAFE_CHARS.contains(c))
+        .collect::<String>()
+        .to_lowercase();
+    folded.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .trim_end_matches('.')
+        .trim()
+        .to_owned()
+}
+
+// `aliases.toml` of every artist directory, the more preferred directories win
+fn load_all_aliases() -> HashMap<String, String> {
+    let mut aliases = HashMap::new();
+    for dir in paths::artist_dirs().iter().rev() {
+        aliases.extend(load_aliases(&dir.join("aliases.toml")));
+    }
+    aliases
+}
+
+// The alias file maps variant spellings to one image name, e.g.
+// "beyonce knowles" = "beyonce"
+fn load_aliases(path: &Path) -> HashMap<String, String> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            info!("Not loading artist aliases {}: {}", path.display(), e);
+            return HashMap::new();
+        },
+    };
+    match toml::from_str::<HashMap<String, String>>(&contents) {
+        Ok(aliases) => aliases.into_iter()
+            .map(|(variant, name)| (normalize(&variant), name.trim().to_lowercase()))
+            .collect(),
+        Err(e) => {
+            warn!("Error parsing artist aliases {}: {}", path.display(), e);
+            HashMap::new()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(aliases: &[(&str, &str)]) -> ArtistMatcher {
+        let config = ArtistConfig::default();
+        ArtistMatcher {
+            lookup_order: config.lookup_order,
+            separators: config.separators,
+            featuring: config.featuring,
+            aliases: aliases.iter().map(|(variant, name)| (normalize(variant), name.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn normalizes_names() {
+        assert_eq!(normalize("Beyoncé"), "beyonce");
+        assert_eq!(normalize("AC/DC"), "acdc");
+        assert_eq!(normalize("  Sunn   O)))  "), "sunn o)))");
+        assert_eq!(normalize("Fontaines D.C."), "fontaines d.c");
+    }
+
+    #[test]
+    fn whole_tag_comes_first() {
+        assert_eq!(matcher(&[]).candidates("AC/DC"), ["acdc", "ac", "dc"]);
+        assert_eq!(matcher(&[]).candidates("Beyoncé"), ["beyoncé", "beyonce"]);
+        assert_eq!(matcher(&[]).candidates("Simon & Garfunkel"), ["simon & garfunkel", "simon", "garfunkel"]);
+    }
+
+    #[test]
+    fn strips_featured_artists() {
+        let matcher = matcher(&[]);
+        assert_eq!(matcher.candidates("X feat. Y"), ["x feat. y", "x"]);
+        assert_eq!(matcher.candidates("X (ft. Y & Z)"), ["x (ft. y & z)", "x"]);
+        assert_eq!(matcher.candidates("X featuring Y"), ["x featuring y", "x"]);
+    }
+
+    #[test]
+    fn marker_without_an_artist_after_it_is_part_of_the_name() {
+        let matcher = matcher(&[]);
+        assert_eq!(matcher.candidates("Little Feat"), ["little feat"]);
+        assert_eq!(matcher.candidates("Little Feat feat. Bonnie Raitt"), ["little feat feat. bonnie raitt", "little feat", "little"]);
+    }
+
+    #[test]
+    fn aliases_win() {
+        let matcher = matcher(&[("Beyonce Knowles", "beyonce")]);
+        assert_eq!(matcher.candidates("Beyoncé Knowles"), ["beyonce", "beyoncé knowles", "beyonce knowles"]);
+    }
+}
@@ -0,0 +1,181 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use speedy2d::font::Font;
+use image::{Rgba, RgbaImage};
+use log::{info, warn};
+use crate::paths;
+
+// A font from the first data directory that has it. A missing or broken copy falls back to the
+// built-in one.
+pub fn load_font(relative: &str, embedded: &[u8]) -> Font {
+    load_font_from(paths::find_data_file(relative).as_deref(), embedded)
+}
+
+fn load_font_from(path: Option<&Path>, embedded: &[u8]) -> Font {
+    if let Some(path) = path {
+        match read_font(path) {
+            Ok(font) => {
+                info!("Loaded font {}", path.display());
+                return font;
+            },
+            Err(e) => warn!("Error loading font {}, using the built-in one: {}", path.display(), e),
+        }
+    }
+    // The built-in fonts are checked by the tests
+    Font::new(embedded).expect("built-in font is valid")
+}
+
+// An image from the first data directory that has it. A missing or broken copy falls back to the
+// built-in one, None if that is broken too.
+pub fn load_image(relative: &str, embedded: &[u8]) -> Option<RgbaImage> {
+    load_image_from(relative, paths::find_data_file(relative).as_deref(), embedded)
+}
+
+fn load_image_from(relative: &str, path: Option<&Path>, embedded: &[u8]) -> Option<RgbaImage> {
+    match path {
+        Some(path) => {
+            match read_image(path) {
+                Ok(image) => {
+                    info!("Loaded {}", path.display());
+                    return Some(image);
+                },
+                Err(e) => warn!("Error loading {}, using the built-in copy: {}", path.display(), e),
+            }
+        },
+        None => info!("{} not found in any data directory, using the built-in copy", relative),
+    }
+    match image::load_from_memory(embedded) {
+        Ok(image) => Some(image.into_rgba8()),
+        Err(e) => {
+            warn!("Built-in {} is broken: {}", relative, e);
+            None
+        },
+    }
+}
+
+fn read_font(path: &Path) -> Result<Font, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    Font::new(&bytes).map_err(|e| e.to_string())
+}
+
+fn read_image(path: &Path) -> Result<RgbaImage, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    image::load_from_memory(&bytes).map(|image| image.into_rgba8()).map_err(|e| e.to_string())
+}
+
+// The replacement for a built-in font, None if there is none. An error with the replacement's
+// path if it can't be used.
+pub fn check_font(relative: &str) -> Result<Option<PathBuf>, (PathBuf, String)> {
+    check(relative, |path| read_font(path).map(|_| ()))
+}
+
+pub fn check_image(relative: &str) -> Result<Option<PathBuf>, (PathBuf, String)> {
+    check(relative, |path| read_image(path).map(|_| ()))
+}
+
+fn check(relative: &str, read: impl FnOnce(&Path) -> Result<(), String>) -> Result<Option<PathBuf>, (PathBuf, String)> {
+    match paths::find_data_file(relative) {
+        Some(path) => match read(&path) {
+            Ok(()) => Ok(Some(path)),
+            Err(e) => Err((path, e)),
+        },
+        None => Ok(None),
+    }
+}
+
+// Album art for songs without any, when even the default art can't be loaded: a grey square with
+// a record in the middle
+pub fn placeholder_cover() -> RgbaImage {
+    const SIZE: u32 = 512;
+    RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        // Slightly lighter towards the bottom right
+        let shade = 40.0 + 24.0 * (x + y) as f32 / (SIZE * 2) as f32;
+        let (dx, dy) = (x as f32 - SIZE as f32 / 2.0, y as f32 - SIZE as f32 / 2.0);
+        let distance = (dx * dx + dy * dy).sqrt() / SIZE as f32;
+        let value = match distance {
+            d if d < 0.02 => shade,
+            d if d < 0.1 => 110.0,
+            d if d < 0.35 && ((d * 100.0) as u32).is_multiple_of(3) => 30.0,
+            d if d < 0.35 => 20.0,
+            _ => shade,
+        };
+        Rgba([value as u8, value as u8, value as u8, 255])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use speedy2d::font::{TextLayout, TextOptions};
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        RgbaImage::new(width, height).write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn built_in_assets_are_valid() {
+        assert!(Font::new(paths::EMBEDDED_FONT_LIGHT).is_ok());
+        assert!(Font::new(paths::EMBEDDED_FONT_BOLD).is_ok());
+        assert!(image::load_from_memory(paths::EMBEDDED_LOGO).is_ok());
+        assert!(image::load_from_memory(paths::EMBEDDED_ART_BACKUP).is_ok());
+    }
+
+    #[test]
+    fn missing_image_uses_the_built_in_one() {
+        let image = load_image_from("assets/logo.png", None, paths::EMBEDDED_LOGO).unwrap();
+        assert_eq!(image.dimensions(), image::load_from_memory(paths::EMBEDDED_LOGO).unwrap().into_rgba8().dimensions());
+    }
+
+    #[test]
+    fn replacement_image_is_preferred() {
+        let dir = TestDir::new("assets");
+        let path = dir.write("logo.png", png(3, 2));
+        let image = load_image_from("assets/logo.png", Some(&path), paths::EMBEDDED_LOGO).unwrap();
+        assert_eq!(image.dimensions(), (3, 2));
+    }
+
+    #[test]
+    fn corrupt_image_uses_the_built_in_one() {
+        let dir = TestDir::new("assets");
+        let path = dir.write("art_backup", b"\x89PNG but not really");
+        let image = load_image_from("assets/art_backup", Some(&path), &png(4, 4)).unwrap();
+        assert_eq!(image.dimensions(), (4, 4));
+    }
+
+    #[test]
+    fn unreadable_image_uses_the_built_in_one() {
+        let dir = TestDir::new("assets");
+        let path = dir.path().join("gone").join("logo.png");
+        let image = load_image_from("assets/logo.png", Some(&path), &png(5, 5)).unwrap();
+        assert_eq!(image.dimensions(), (5, 5));
+    }
+
+    #[test]
+    fn broken_built_in_image() {
+        assert!(load_image_from("assets/logo.png", None, b"not an image").is_none());
+        let dir = TestDir::new("assets");
+        let path = dir.write("broken.png", b"also not an image");
+        assert!(load_image_from("assets/logo.png", Some(&path), b"not an image").is_none());
+    }
+
+    #[test]
+    fn placeholder_cover_is_square() {
+        let cover = placeholder_cover();
+        assert_eq!(cover.width(), cover.height());
+        // Not just a flat color
+        assert_ne!(cover.get_pixel(0, 0), cover.get_pixel(cover.width() / 2 - 60, cover.height() / 2));
+    }
+
+    #[test]
+    fn corrupt_font_uses_the_built_in_one() {
+        let dir = TestDir::new("assets");
+        let path = dir.write("font.otf", b"OTTO but not really");
+        let font = load_font_from(Some(&path), paths::EMBEDDED_FONT_BOLD);
+        assert!(font.layout_text("Up next:", 20.0, TextOptions::new()).width() > 0.0);
+        let font = load_font_from(None, paths::EMBEDDED_FONT_LIGHT);
+        assert!(font.layout_text("Up next:", 20.0, TextOptions::new()).width() > 0.0);
+    }
+}
@@ -0,0 +1,302 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use speedy2d::Graphics2D;
+use speedy2d::color::Color;
+use speedy2d::shape::Rectangle;
+use speedy2d::image::{ImageHandle, ImageDataType, ImageSmoothingMode};
+use log::{info, warn};
+use crate::config::BackgroundConfig;
+use crate::paths;
+// In order of preference when an artist has more than one single-file background
+const BACKGROUND_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "webp"];
+
+// Directions the Ken Burns effect pans in, cycled through slide by slide
+const PAN_DIRECTIONS: [(f32, f32); 4] = [(1.0, 0.3), (-0.8, 0.6), (0.4, -1.0), (-0.6, -0.8)];
+
+// Background image(s) for the current artist.
+// Either a single `artists/<name>.<ext>` drawn as-is, or every image in `artists/<name>/` shown as a
+// slow panning/zooming slideshow.
+pub struct ArtistBackground {
+    images: Vec<ImageHandle>,
+    slideshow: bool,
+    shown_at: Instant,
+}
+
+// Where the background images for an artist name were found
+pub enum BackgroundSource {
+    Directory(PathBuf, Vec<PathBuf>),
+    File(PathBuf),
+}
+
+impl std::fmt::Display for BackgroundSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BackgroundSource::Directory(dir, paths) => write!(f, "{} ({} images)", dir.display(), paths.len()),
+            BackgroundSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+// The contents of every artist directory, read once per lookup so trying a dozen candidate names
+// for a song doesn't list and stat the directories a dozen times
+pub struct ArtistImages {
+    dirs: Vec<Vec<Entry>>,
+}
+
+struct Entry {
+    path: PathBuf,
+    is_dir: bool,
+    // Lowercase, for matching names case-insensitively
+    name: String,
+    stem: String,
+    extension: String,
+}
+
+impl ArtistImages {
+    pub fn list() -> ArtistImages {
+        ArtistImages::list_dirs(&paths::artist_dirs())
+    }
+
+    fn list_dirs(artist_dirs: &[PathBuf]) -> ArtistImages {
+        ArtistImages {
+            dirs: artist_dirs.iter().map(|dir| list_entries(dir)).collect(),
+        }
+    }
+
+    // Looks for `artists/<name>/` with at least one image in it, then `artists/<name>.<ext>`, in
+    // each artist directory in turn. File and directory names are matched case-insensitively.
+    pub fn find(&self, name: &str) -> Option<BackgroundSource> {
+        self.dirs.iter().find_map(|entries| find_in(entries, name))
+    }
+}
+
+fn list_entries(artists_dir: &Path) -> Vec<Entry> {
+    let entries = match fs::read_dir(artists_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("error reading artist directory {}: {}", artists_dir.display(), e);
+            return Vec::new();
+        },
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let path = entry.path();
+            // Symlinks are followed, anything else is known from the listing without a stat
+            let is_dir = match entry.file_type() {
+                Ok(file_type) if file_type.is_symlink() => path.is_dir(),
+                Ok(file_type) => file_type.is_dir(),
+                Err(_) => false,
+            };
+            Entry {
+                is_dir,
+                name: lowercase_name(path.file_name()),
+                stem: lowercase_name(path.file_stem()),
+                extension: lowercase_name(path.extension()),
+                path,
+            }
+        })
+        .collect()
+}
+
+fn find_in(entries: &[Entry], name: &str) -> Option<BackgroundSource> {
+    let name = name.to_lowercase();
+    for dir in entries.iter().filter(|entry| entry.is_dir && entry.name == name) {
+        let paths = slideshow_paths(&dir.path);
+        if !paths.is_empty() {
+            return Some(BackgroundSource::Directory(dir.path.clone(), paths));
+        }
+    }
+    for extension in BACKGROUND_EXTENSIONS {
+        let file = entries.iter().find(|entry| !entry.is_dir && entry.stem == name && entry.extension == extension);
+        if let Some(file) = file {
+            return Some(BackgroundSource::File(file.path.clone()));
+        }
+    }
+    None
+}
+
+fn lowercase_name(name: Option<&std::ffi::OsStr>) -> String {
+    match name {
+        Some(name) => name.to_string_lossy().to_lowercase(),
+        None => "".to_owned(),
+    }
+}
+
+impl ArtistBackground {
+    pub fn load(ctx: &mut Graphics2D, source: &BackgroundSource) -> Option<ArtistBackground> {
+        match source {
+            BackgroundSource::Directory(dir, paths) => {
+                let images: Vec<ImageHandle> = paths.iter()
+                    .filter_map(|path| match load_image(ctx, path) {
+                        Ok(handle) => Some(handle),
+                        Err(e) => {
+                            warn!("error loading background image {}: {}", path.display(), e);
+                            None
+                        },
+                    })
+                    .collect();
+                if images.is_empty() {
+                    return None;
+                }
+                info!("Loaded {} background images from {}", images.len(), dir.display());
+                Some(ArtistBackground { images, slideshow: true, shown_at: Instant::now() })
+            },
+            BackgroundSource::File(path) => match load_image(ctx, path) {
+                Ok(handle) => Some(ArtistBackground { images: vec![handle], slideshow: false, shown_at: Instant::now() }),
+                Err(e) => {
+                    warn!("error loading background image {}: {}", path.display(), e);
+                    None
+                },
+            },
+        }
+    }
+
+    pub fn draw(&self, graphics: &mut Graphics2D, viewport: (f32, f32), tint: Color, config: &BackgroundConfig) {
+        if !self.slideshow {
+            draw_cover(graphics, viewport, tint, &self.images[0]);
+            return;
+        }
+        let viewport_rect = Rectangle::from_tuples((0.0, 0.0), viewport);
+        let interval = config.slideshow_interval_secs.max(1.0);
+        let elapsed = self.shown_at.elapsed().as_secs_f32();
+        let index = (elapsed / interval) as usize;
+        let slide_elapsed = elapsed - index as f32 * interval;
+        let progress = slide_elapsed / interval;
+
+        let fade = config.fade_secs.min(interval);
+        if index > 0 && self.images.len() > 1 && slide_elapsed < fade {
+            // Keep the previous slide moving underneath while the new one fades in
+            let previous = index - 1;
+            let handle = &self.images[previous % self.images.len()];
+            let subset = ken_burns_subset(previous, 1.0 + progress, handle, viewport, config);
+            graphics.draw_rectangle_image_subset_tinted(viewport_rect.clone(), tint, subset, handle);
+            let alpha = slide_elapsed / fade;
+            let handle = &self.images[index % self.images.len()];
+            let subset = ken_burns_subset(index, progress, handle, viewport, config);
+            let faded_tint = Color::from_rgba(tint.r(), tint.g(), tint.b(), tint.a() * alpha);
+            graphics.draw_rectangle_image_subset_tinted(viewport_rect, faded_tint, subset, handle);
+        } else {
+            let handle = &self.images[index % self.images.len()];
+            let subset = ken_burns_subset(index, progress, handle, viewport, config);
+            graphics.draw_rectangle_image_subset_tinted(viewport_rect, tint, subset, handle);
+        }
+    }
+}
+
+// Images in a per-artist directory, sorted so the slideshow order is stable
+fn slideshow_paths(dir: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("error reading background directory {}: {}", dir.display(), e);
+            return Vec::new();
+        },
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && BACKGROUND_EXTENSIONS.contains(&lowercase_name(path.extension()).as_str()))
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn load_image(ctx: &mut Graphics2D, path: &Path) -> Result<ImageHandle, String> {
+    let decoded = image::open(path).map_err(|e| e.to_string())?.into_rgba8();
+    ctx.create_image_from_raw_pixels(ImageDataType::RGBA, ImageSmoothingMode::Linear, decoded.dimensions(), decoded.as_raw())
+        .map_err(|e| e.to_string())
+}
+
+// Scales the image to cover the whole viewport, centered
+fn draw_cover(graphics: &mut Graphics2D, viewport: (f32, f32), tint: Color, handle: &ImageHandle) {
+    let (width, height) = viewport;
+    let img_dims = handle.size();
+    let mut x_offset = 0.0;
+    let mut y_offset = 0.0;
+    let mut scale = width / img_dims.x as f32;
+    if (img_dims.y as f32 * scale) < height {
+        scale = height / img_dims.y as f32;
+        x_offset = (width / -2.0) + (img_dims.x as f32 * scale / 2.0);
+    }
+    else {
+        y_offset = (height / -2.0) + (img_dims.y as f32 * scale / 2.0);
+    }
+    let rect = crate::get_scaled_image_rect(handle, scale, (-x_offset, -y_offset));
+    graphics.draw_rectangle_image_tinted(rect, tint, handle);
+}
+
+// Normalized part of the image to show for a slide at `progress` (0.0 to 1.0) through its interval.
+// Starts from the same crop as `draw_cover`, then zooms (alternating in and out) and pans across it.
+fn ken_burns_subset(index: usize, progress: f32, handle: &ImageHandle, viewport: (f32, f32), config: &BackgroundConfig) -> Rectangle<f32> {
+    let image_ratio = handle.size().x as f32 / handle.size().y as f32;
+    let viewport_ratio = viewport.0 / viewport.1;
+    let (mut width, mut height) = if image_ratio > viewport_ratio {
+        (viewport_ratio / image_ratio, 1.0)
+    } else {
+        (1.0, image_ratio / viewport_ratio)
+    };
+    let zoom = match index % 2 {
+        0 => 1.0 + config.ken_burns_zoom * progress,
+        _ => 1.0 + config.ken_burns_zoom * (1.0 - progress),
+    }.max(1.0);
+    width /= zoom;
+    height /= zoom;
+    let (pan_x, pan_y) = PAN_DIRECTIONS[index % PAN_DIRECTIONS.len()];
+    let center_x = (0.5 + pan_x * config.ken_burns_pan * (progress - 0.5)).clamp(width / 2.0, 1.0 - width / 2.0);
+    let center_y = (0.5 + pan_y * config.ken_burns_pan * (progress - 0.5)).clamp(height / 2.0, 1.0 - height / 2.0);
+    Rectangle::from_tuples(
+        (center_x - width / 2.0, center_y - height / 2.0),
+        (center_x + width / 2.0, center_y + height / 2.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn artists_dir(name: &str, files: &[&str]) -> TestDir {
+        let dir = TestDir::new(name);
+        for file in files {
+            dir.write(file, b"");
+        }
+        dir
+    }
+
+    #[test]
+    fn matches_names_case_insensitively() {
+        let artists = artists_dir("case", &["Beyonce.PNG", "acdc.webp", "acdc.jpg"]);
+        let dir = artists.path();
+        let images = ArtistImages::list_dirs(&[dir.to_path_buf()]);
+        assert!(matches!(images.find("beyonce"), Some(BackgroundSource::File(path)) if path == dir.join("Beyonce.PNG")));
+        assert!(matches!(images.find("acdc"), Some(BackgroundSource::File(path)) if path == dir.join("acdc.jpg")));
+        assert!(images.find("queen").is_none());
+    }
+
+    #[test]
+    fn directories_win_over_files() {
+        let artists = artists_dir("slideshow", &["Queen.jpg", "Queen/2.png", "Queen/1.jpg", "Queen/notes.txt", "empty.jpg"]);
+        let dir = artists.path();
+        fs::create_dir_all(dir.join("empty")).unwrap();
+        let images = ArtistImages::list_dirs(&[dir.to_path_buf()]);
+        match images.find("queen") {
+            Some(BackgroundSource::Directory(path, paths)) => {
+                assert_eq!(path, dir.join("Queen"));
+                assert_eq!(paths, [dir.join("Queen/1.jpg"), dir.join("Queen/2.png")]);
+            },
+            _ => panic!("expected the Queen directory"),
+        }
+        // A directory without images falls back to the file
+        assert!(matches!(images.find("empty"), Some(BackgroundSource::File(_))));
+    }
+
+    #[test]
+    fn earlier_directories_win() {
+        let preferred = artists_dir("preferred", &["queen.png"]);
+        let other = artists_dir("other", &["queen.jpg", "abba.jpg"]);
+        let images = ArtistImages::list_dirs(&[preferred.path().to_path_buf(), other.path().to_path_buf()]);
+        assert!(matches!(images.find("queen"), Some(BackgroundSource::File(path)) if path == preferred.path().join("queen.png")));
+        assert!(matches!(images.find("abba"), Some(BackgroundSource::File(path)) if path == other.path().join("abba.jpg")));
+    }
+}
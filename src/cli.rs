@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: mpd-display-rs [OPTIONS]
+
+Options:
+  --which-background [ARTIST]  Print which background image would be used for ARTIST
+                               (or the current song if left out) and exit
+  --check                      Check the config, assets and MPD connection without opening
+                               a window and exit
+  --screenshot FILE            Render one frame to a PNG file without opening a window and exit
+  --size WIDTHxHEIGHT          Size of the screenshot (default 1920x1080)
+  --fixture FILE               Take the screenshot of the song state in this TOML file
+                               instead of what MPD is playing
+  -h, --help                   Print this help and exit
+
+Exit codes:
+  1  Taking the screenshot failed     5  Can't connect to MPD
+  2  Invalid arguments                6  MPD rejected the password
+  3  Invalid config file              7  Unexpected reply from MPD
+  4  Invalid replacement asset        8  No display to open the window on
+                                     9  Can't read MPD_PASSWORD_FILE";
+
+#[derive(Debug, Default)]
+pub struct Args {
+    // `Some(None)` means the artist of the current song
+    pub which_background: Option<Option<String>>,
+    pub check: bool,
+    pub screenshot: Option<PathBuf>,
+    pub size: Option<String>,
+    pub fixture: Option<PathBuf>,
+    pub help: bool,
+}
+
+impl Args {
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--which-background" => {
+                    let artist = args.next_if(|next| !next.starts_with("--"));
+                    parsed.which_background = Some(artist);
+                },
+                "--screenshot" | "--size" | "--fixture" => {
+                    let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                    match arg.as_str() {
+                        "--screenshot" => parsed.screenshot = Some(PathBuf::from(value)),
+                        "--size" => parsed.size = Some(value),
+                        _ => parsed.fixture = Some(PathBuf::from(value)),
+                    }
+                },
+                "--check" => {
+                    parsed.check = true;
+                },
+                "-h" | "--help" => {
+                    parsed.help = true;
+                },
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+        if parsed.screenshot.is_none() && (parsed.size.is_some() || parsed.fixture.is_some()) {
+            return Err("--size and --fixture only work with --screenshot".to_owned());
+        }
+        Ok(parsed)
+    }
+}
@@ -0,0 +1,64 @@
+use std::fmt::Write;
+use chrono::Local;
+use chrono::format::{Item, StrftimeItems};
+use speedy2d::Graphics2D;
+use speedy2d::color::Color;
+use speedy2d::dimen::Vec2;
+use speedy2d::font::{Font, TextLayout, TextOptions};
+use speedy2d::shape::Rectangle;
+use crate::config::ClockConfig;
+use crate::layout::overlaps;
+use crate::theme::Position;
+
+// Formats the current local time with a strftime format string. An invalid format falls back to
+// the plain 24 hour time instead of failing.
+pub fn format_now(format: &str) -> String {
+    let now = Local::now();
+    let mut formatted = String::new();
+    if StrftimeItems::new(format).any(|item| item == Item::Error)
+        || write!(formatted, "{}", now.format(format)).is_err() {
+        return now.format("%H:%M").to_string();
+    }
+    formatted
+}
+
+// Draws the time (and the date underneath, if it has a format) at the configured position. If that
+// overlaps one of the `avoid` rectangles the clock is moved away from the edge it's anchored to
+// until it doesn't.
+pub fn draw(graphics: &mut Graphics2D, config: &ClockConfig, viewport: Vec2, font_bold: &Font, font_light: &Font, color: Color, avoid: &[Rectangle]) {
+    let time_format = match &config.format {
+        Some(format) => format.as_str(),
+        None if config.hour12 => "%-I:%M %p",
+        None => "%H:%M",
+    };
+    let text_time = font_bold.layout_text(&format_now(time_format), config.time_size.resolve(viewport), TextOptions::new());
+    let text_date = match config.date_format.as_str() {
+        "" => None,
+        format => Some(font_light.layout_text(&format_now(format), config.date_size.resolve(viewport), TextOptions::new())),
+    };
+    let date_size = match &text_date {
+        Some(text) => text.size(),
+        None => Vec2::ZERO,
+    };
+    let size = Vec2::new(text_time.width().max(date_size.x), text_time.height() + date_size.y);
+    let anchor = Position { x: config.x.clone(), y: config.y.clone(), anchor: config.anchor };
+    let mut position = anchor.top_left(viewport, size);
+    let move_up = config.anchor.is_bottom();
+    // Every move clears one rectangle, so this can't take more moves than there are rectangles
+    for _ in 0..avoid.len() {
+        let rect = Rectangle::new(position, position + size);
+        let obstacle = match avoid.iter().find(|other| overlaps(&rect, other)) {
+            Some(obstacle) => obstacle,
+            None => break,
+        };
+        position.y = match move_up {
+            true => obstacle.top() - size.y,
+            false => obstacle.bottom(),
+        };
+    }
+    let aligned_x = |width: f32| position.x + (size.x - width) / 2.0;
+    graphics.draw_text((aligned_x(text_time.width()), position.y), color, &text_time);
+    if let Some(text) = &text_date {
+        graphics.draw_text((aligned_x(text.width()), position.y + text_time.height()), color, text);
+    }
+}
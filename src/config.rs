@@ -0,0 +1,424 @@
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use speedy2d::color::Color;
+use log::{info, warn};
+use crate::theme::{Anchor, Length};
+
+// Everything in here is optional, a missing file or key falls back to the defaults below
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Name of the layout theme to start with
+    pub theme: String,
+    // Name of the server profile to start with, the first one if empty
+    pub server: String,
+    // MPD instances to switch between. Without any, MPD_HOST and MPD_PORT are used.
+    pub servers: Vec<ServerConfig>,
+    // MPD partition to show, the default one if empty. A server profile's own wins.
+    pub partition: String,
+    pub notifications: NotificationConfig,
+    pub background: BackgroundConfig,
+    pub artists: ArtistConfig,
+    pub colors: ColorConfig,
+    pub idle: IdleConfig,
+    pub clock: ClockConfig,
+    pub visualizer: VisualizerConfig,
+    pub volume: VolumeConfig,
+    pub controls: ControlsConfig,
+    pub gestures: GestureConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            theme: "spotify".to_owned(),
+            server: String::new(),
+            servers: Vec::new(),
+            partition: String::new(),
+            notifications: NotificationConfig::default(),
+            background: BackgroundConfig::default(),
+            artists: ArtistConfig::default(),
+            colors: ColorConfig::default(),
+            idle: IdleConfig::default(),
+            clock: ClockConfig::default(),
+            visualizer: VisualizerConfig::default(),
+            volume: VolumeConfig::default(),
+            controls: ControlsConfig::default(),
+            gestures: GestureConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    // Shown in the header instead of "MPD QUEUE"
+    pub name: String,
+    #[serde(default = "localhost")]
+    pub host: String,
+    #[serde(default = "mpd_port")]
+    pub port: u16,
+    // Read from a file instead of being written here, wins over `password` if both are set
+    pub password_file: Option<PathBuf>,
+    pub password: Option<String>,
+    pub partition: Option<String>,
+}
+
+fn localhost() -> String { "localhost".to_owned() }
+fn mpd_port() -> u16 { 6600 }
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    pub enabled: bool,
+    // Only notify while the display is windowed, the fullscreen display already shows the song
+    pub windowed_only: bool,
+    // How long a song has to stay current before it gets a notification, so skipping through
+    // the queue doesn't spam one notification per track
+    pub debounce_ms: u64,
+    pub timeout_ms: u32,
+    // Album art is scaled down to fit in a square this size before being sent over D-Bus
+    pub icon_size: u32,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        NotificationConfig {
+            enabled: false,
+            windowed_only: true,
+            debounce_ms: 1500,
+            timeout_ms: 5000,
+            icon_size: 128,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BackgroundConfig {
+    // Seconds each image of an artist directory is shown for
+    pub slideshow_interval_secs: f32,
+    // Seconds the crossfade between two slides takes
+    pub fade_secs: f32,
+    // How far each slide zooms over its interval, 0.15 ends at 115%
+    pub ken_burns_zoom: f32,
+    // How far each slide pans over its interval, as a fraction of the image size
+    pub ken_burns_pan: f32,
+}
+
+impl Default for BackgroundConfig {
+    fn default() -> Self {
+        BackgroundConfig {
+            slideshow_interval_secs: 30.0,
+            fade_secs: 2.0,
+            ken_burns_zoom: 0.15,
+            ken_burns_pan: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ArtistConfig {
+    // Tags tried in order when looking for a background image
+    pub lookup_order: Vec<LookupSource>,
+    // Strings separating multiple artists in one artist tag, matched case-insensitively
+    pub separators: Vec<String>,
+    // Markers for featured artists, everything from the marker onwards is ignored when another
+    // artist follows it
+    pub featuring: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LookupSource {
+    // MUSICBRAINZ_ARTISTID, matched against `artists/<id>.jpg`
+    Musicbrainz,
+    AlbumArtist,
+    // Every artist in the Artist tag
+    Artist,
+}
+
+impl Default for ArtistConfig {
+    fn default() -> Self {
+        ArtistConfig {
+            lookup_order: vec![LookupSource::Musicbrainz, LookupSource::AlbumArtist, LookupSource::Artist],
+            separators: [", ", "/", " & ", "; ", " x ", " vs. ", " vs "].iter().map(|s| s.to_string()).collect(),
+            featuring: ["feat.", "feat", "ft.", "ft", "featuring"].iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ColorConfig {
+    // "PLAYING FROM", queue length and the empty part of the progress bar
+    pub text_background: HexColor,
+    // Title, timestamps and the filled part of the progress bar
+    pub text_foreground: HexColor,
+    // Artist
+    pub text_midground: HexColor,
+    // Multiplied with the artist background image to darken it
+    pub background_image_tint: HexColor,
+    // Used when there is no artist background image
+    pub background: HexColor,
+    // Progress bar while hovered
+    pub accent: HexColor,
+}
+
+impl Default for ColorConfig {
+    fn default() -> Self {
+        ColorConfig {
+            text_background: HexColor(Color::from_int_rgb(156,156,156)),
+            text_foreground: HexColor(Color::from_int_rgb(255,255,255)),
+            text_midground: HexColor(Color::from_int_rgb(195,195,195)),
+            background_image_tint: HexColor(Color::from_int_rgba(75, 75, 75, 255)),
+            background: HexColor(Color::from_int_rgb(50,50,50)),
+            accent: HexColor(Color::from_int_rgb(29, 185, 84)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IdleConfig {
+    // Minutes of being stopped, paused or having an empty queue before the idle screen is shown,
+    // 0 disables it
+    pub after_minutes: f32,
+    pub mode: IdleMode,
+    // How fast the clock or album art drifts around, as a fraction of the shorter screen side per
+    // second
+    pub drift_speed: f32,
+    // strftime format of the idle clock
+    pub clock_format: String,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        IdleConfig {
+            after_minutes: 10.0,
+            mode: IdleMode::Clock,
+            drift_speed: 0.02,
+            clock_format: "%H:%M".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdleMode {
+    // The time drifting around the screen
+    Clock,
+    // The last album art drifting around the screen
+    Art,
+    // Nothing but black
+    Blank,
+}
+
+// Time and date shown on top of the display
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ClockConfig {
+    pub enabled: bool,
+    // Only used when there's no format, picks between "%-I:%M %p" and "%H:%M"
+    pub hour12: bool,
+    // strftime format of the time
+    pub format: Option<String>,
+    // strftime format of the date underneath the time, empty to hide it
+    pub date_format: String,
+    // Positioned like theme elements, the clock is moved out of the way if it would overlap the
+    // header or the up next card
+    pub x: Length,
+    pub y: Length,
+    pub anchor: Anchor,
+    pub time_size: Length,
+    pub date_size: Length,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        ClockConfig {
+            enabled: false,
+            hour12: false,
+            format: None,
+            date_format: "%A %-d %B".to_owned(),
+            x: Length::default_from("0.5w"),
+            y: Length::default_from("0.0444h"),
+            anchor: Anchor::TopCenter,
+            time_size: Length::default_from("0.07h"),
+            date_size: Length::default_from("0.03h"),
+        }
+    }
+}
+
+// Bars drawn from the PCM MPD writes to a FIFO output, which has to use 16 bit samples
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct VisualizerConfig {
+    pub enabled: bool,
+    // `path` of the fifo audio_output in mpd.conf
+    pub fifo_path: PathBuf,
+    // Have to match the `format` of the fifo audio_output ("44100:16:2")
+    pub sample_rate: u32,
+    pub channels: usize,
+    pub bars: usize,
+    // How much of the previous level is kept every update, between 0.0 (jumpy) and 0.99 (sluggish)
+    pub smoothing: f32,
+    pub style: VisualizerStyle,
+    pub placement: VisualizerPlacement,
+}
+
+impl Default for VisualizerConfig {
+    fn default() -> Self {
+        VisualizerConfig {
+            enabled: false,
+            fifo_path: PathBuf::from("/tmp/mpd.fifo"),
+            sample_rate: 44100,
+            channels: 2,
+            bars: 32,
+            smoothing: 0.6,
+            style: VisualizerStyle::Spectrum,
+            placement: VisualizerPlacement::Behind,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VisualizerStyle {
+    // Level of each frequency band
+    Spectrum,
+    // Peak level over time
+    Waveform,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VisualizerPlacement {
+    // Faintly behind the title and artist
+    Behind,
+    // In a strip underneath the artist
+    Beneath,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct VolumeConfig {
+    // Percent the volume changes per notch of the scroll wheel, 0 disables scrolling
+    pub step: u8,
+}
+
+impl Default for VolumeConfig {
+    fn default() -> Self {
+        VolumeConfig {
+            step: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ControlsConfig {
+    // Previous, play/pause and next buttons, placed by the theme's `transport`
+    pub transport_buttons: bool,
+    // Clicking anywhere else toggles pause
+    pub click_to_pause: bool,
+}
+
+impl Default for ControlsConfig {
+    fn default() -> Self {
+        ControlsConfig {
+            transport_buttons: false,
+            click_to_pause: true,
+        }
+    }
+}
+
+// Touch gestures, distances are fractions of the shorter side of the window
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GestureConfig {
+    pub enabled: bool,
+    // Moving at least this far is a swipe
+    pub swipe_distance: f32,
+    // Moving at most this far is still a tap or long press
+    pub tap_distance: f32,
+    pub long_press_ms: u64,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        GestureConfig {
+            enabled: false,
+            swipe_distance: 0.15,
+            tap_distance: 0.03,
+            long_press_ms: 700,
+        }
+    }
+}
+
+// A color written as "#rrggbb" or "#rrggbbaa"
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct HexColor(pub Color);
+
+impl TryFrom<String> for HexColor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let hex = value.trim_start_matches('#');
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2).unwrap_or("xx"), 16)
+            .map_err(|_| format!("invalid color {:?}, expected \"#rrggbb\" or \"#rrggbbaa\"", value));
+        match hex.len() {
+            6 => Ok(HexColor(Color::from_int_rgb(channel(0)?, channel(2)?, channel(4)?))),
+            8 => Ok(HexColor(Color::from_int_rgba(channel(0)?, channel(2)?, channel(4)?, channel(6)?))),
+            _ => Err(format!("invalid color {:?}, expected \"#rrggbb\" or \"#rrggbbaa\"", value)),
+        }
+    }
+}
+
+impl Config {
+    // Falls back to the defaults when the file is broken, so editing it while the display is
+    // running can't take it down
+    pub fn load(path: &Path) -> Config {
+        match Config::try_load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Error in config file {}, using defaults: {}", path.display(), e);
+                Config::default()
+            },
+        }
+    }
+
+    // The defaults if there is no config file, an error if it can't be read or parsed
+    pub fn try_load(path: &Path) -> Result<Config, String> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("Not loading config file {}: {}", path.display(), e);
+                return Ok(Config::default());
+            },
+            Err(e) => return Err(e.to_string()),
+        };
+        let config: Config = toml::from_str(&contents).map_err(|e| e.to_string())?;
+        if !config.server.is_empty() && config.start_server().is_none() {
+            return Err(format!("server {:?} isn't one of the [[servers]]", config.server));
+        }
+        Ok(config)
+    }
+
+    // Partition to switch to after connecting to `server`, None for the default one
+    pub fn partition_for<'a>(&'a self, server: Option<&'a ServerConfig>) -> Option<&'a str> {
+        server.and_then(|server| server.partition.as_deref())
+            .or(Some(self.partition.as_str()))
+            .filter(|partition| !partition.is_empty())
+    }
+
+    // The server profile to start with, None without any profiles
+    pub fn start_server(&self) -> Option<&ServerConfig> {
+        match self.server.is_empty() {
+            true => self.servers.first(),
+            false => self.servers.iter().find(|server| server.name == self.server),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::ffi::{c_void, CStr, CString};
+use std::ptr;
+use glutin_egl_sys::egl;
+use glutin_egl_sys::egl::types::{EGLConfig, EGLContext, EGLDisplay, EGLSurface, EGLint};
+use libloading::Library;
+use speedy2d::dimen::UVec2;
+
+// EGL_MESA_platform_surfaceless, which the bindings don't include: a display that isn't
+// connected to any window system. Mesa picks a GPU if there is one and llvmpipe otherwise.
+const PLATFORM_SURFACELESS_MESA: u32 = 0x31DD;
+
+// An OpenGL context drawing into an offscreen pbuffer, made current on the thread that created
+// it. Needs nothing but libEGL from Mesa, no display and no GPU.
+pub struct HeadlessContext {
+    egl: egl::Egl,
+    display: EGLDisplay,
+    surface: EGLSurface,
+    context: EGLContext,
+    // The function pointers in `egl` point into it
+    _library: Library,
+}
+
+impl HeadlessContext {
+    pub fn new(size: UVec2) -> Result<HeadlessContext, String> {
+        let library = unsafe { Library::new("libEGL.so.1").or_else(|_| Library::new("libEGL.so")) }
+            .map_err(|e| format!("error loading libEGL: {}", e))?;
+        let egl = egl::Egl::load_with(|name| {
+            let name = CString::new(name).expect("EGL function names have no NULs");
+            unsafe { library.get::<*const c_void>(name.as_bytes_with_nul()) }
+                .map(|symbol| *symbol)
+                .unwrap_or(ptr::null())
+        });
+        unsafe { HeadlessContext::create(egl, library, size) }
+    }
+
+    unsafe fn create(egl: egl::Egl, library: Library, size: UVec2) -> Result<HeadlessContext, String> {
+        if !egl.GetPlatformDisplay.is_loaded() {
+            return Err("libEGL is older than EGL 1.5".to_owned());
+        }
+        let extensions = egl.QueryString(egl::NO_DISPLAY, egl::EXTENSIONS as EGLint);
+        if extensions.is_null() || !CStr::from_ptr(extensions).to_string_lossy().split(' ').any(|name| name == "EGL_MESA_platform_surfaceless") {
+            return Err("libEGL can't render without a window system (no EGL_MESA_platform_surfaceless)".to_owned());
+        }
+        let display = egl.GetPlatformDisplay(PLATFORM_SURFACELESS_MESA, egl::DEFAULT_DISPLAY as *mut c_void, [egl::NONE as isize].as_ptr());
+        if display == egl::NO_DISPLAY || egl.Initialize(display, ptr::null_mut(), ptr::null_mut()) == egl::FALSE {
+            return Err(format!("error initializing the EGL display (0x{:x})", egl.GetError()));
+        }
+        // Everything created so far is cleaned up by drop() from here on
+        let mut context = HeadlessContext {
+            egl,
+            display,
+            surface: egl::NO_SURFACE,
+            context: egl::NO_CONTEXT,
+            _library: library,
+        };
+        let egl = &context.egl;
+        if egl.BindAPI(egl::OPENGL_API) == egl::FALSE {
+            return Err(format!("error selecting desktop OpenGL (0x{:x})", egl.GetError()));
+        }
+        let config_attributes = [
+            egl::SURFACE_TYPE, egl::PBUFFER_BIT,
+            egl::RENDERABLE_TYPE, egl::OPENGL_BIT,
+            egl::RED_SIZE, 8,
+            egl::GREEN_SIZE, 8,
+            egl::BLUE_SIZE, 8,
+            egl::ALPHA_SIZE, 8,
+            egl::NONE,
+        ].map(|value| value as EGLint);
+        let mut config: EGLConfig = ptr::null();
+        let mut configs = 0;
+        if egl.ChooseConfig(display, config_attributes.as_ptr(), &mut config, 1, &mut configs) == egl::FALSE || configs == 0 {
+            return Err("no EGL config for an offscreen RGBA surface".to_owned());
+        }
+        let surface_attributes = [egl::WIDTH as EGLint, size.x as EGLint, egl::HEIGHT as EGLint, size.y as EGLint, egl::NONE as EGLint];
+        context.surface = egl.CreatePbufferSurface(display, config, surface_attributes.as_ptr());
+        if context.surface == egl::NO_SURFACE {
+            return Err(format!("error creating a {}x{} offscreen surface (0x{:x})", size.x, size.y, egl.GetError()));
+        }
+        context.context = egl.CreateContext(display, config, egl::NO_CONTEXT, [egl::NONE as EGLint].as_ptr());
+        if context.context == egl::NO_CONTEXT {
+            return Err(format!("error creating the OpenGL context (0x{:x})", egl.GetError()));
+        }
+        if egl.MakeCurrent(display, context.surface, context.surface, context.context) == egl::FALSE {
+            return Err(format!("error activating the OpenGL context (0x{:x})", egl.GetError()));
+        }
+        Ok(context)
+    }
+
+    pub fn get_proc_address(&self, name: &str) -> *const c_void {
+        let name = match CString::new(name) {
+            Ok(name) => name,
+            Err(_) => return ptr::null(),
+        };
+        unsafe { self.egl.GetProcAddress(name.as_ptr()) as *const c_void }
+    }
+}
+
+impl Drop for HeadlessContext {
+    fn drop(&mut self) {
+        unsafe {
+            self.egl.MakeCurrent(self.display, egl::NO_SURFACE, egl::NO_SURFACE, egl::NO_CONTEXT);
+            if self.context != egl::NO_CONTEXT {
+                self.egl.DestroyContext(self.display, self.context);
+            }
+            if self.surface != egl::NO_SURFACE {
+                self.egl.DestroySurface(self.display, self.surface);
+            }
+            self.egl.Terminate(self.display);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use mpd::error::{Error, ErrorCode};
+
+// Why the display couldn't start. Each kind exits with its own code, so a service manager or
+// script can tell a missing MPD from a broken config. Exit code 2 is taken by usage errors.
+#[derive(Debug)]
+pub enum StartupError {
+    // The config file exists but can't be read or parsed
+    Config { path: PathBuf, message: String },
+    // A replacement asset exists but can't be used, only reported by --check
+    Asset { path: PathBuf, message: String },
+    // Nothing answered at the address
+    Connection { address: String, source: io::Error },
+    // MPD rejected the password, or needs one and none was given
+    Auth { address: String, message: String },
+    // MPD_PASSWORD_FILE can't be read
+    PasswordFile { path: PathBuf, message: String },
+    // MPD answered, but not in a way the display understands
+    Mpd { address: String, source: Error },
+    NoDisplay(String),
+    Screenshot(String),
+}
+
+impl StartupError {
+    // Sorts an error from connecting to or talking to MPD
+    pub fn from_mpd(address: &str, error: Error) -> StartupError {
+        let address = address.to_owned();
+        match error {
+            Error::Io(source) => StartupError::Connection { address, source },
+            Error::Server(e) if e.code == ErrorCode::Password => StartupError::Auth {
+                address,
+                message: format!("wrong password ({})", e.detail),
+            },
+            Error::Server(e) if e.code == ErrorCode::Permission => StartupError::Auth {
+                address,
+                message: format!("permission denied for {} ({}), MPD may need a password", e.command, e.detail),
+            },
+            source => StartupError::Mpd { address, source },
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            StartupError::Screenshot(_) => 1,
+            StartupError::Config { .. } => 3,
+            StartupError::Asset { .. } => 4,
+            StartupError::Connection { .. } => 5,
+            StartupError::Auth { .. } => 6,
+            StartupError::Mpd { .. } => 7,
+            StartupError::NoDisplay(_) => 8,
+            StartupError::PasswordFile { .. } => 9,
+        }
+    }
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StartupError::Config { path, message } => write!(f,
+                "Error in config file {}: {}\nFix it, or move it out of the way to use the defaults.", path.display(), message),
+            StartupError::Asset { path, message } => write!(f,
+                "Error loading {}, the built-in copy would be used instead: {}", path.display(), message),
+            StartupError::Connection { address, source } => match source.kind() {
+                io::ErrorKind::ConnectionRefused => write!(f,
+                    "MPD refused the connection at {}. Is it running?\nCheck MPD_HOST and MPD_PORT, or the server profile in the config.", address),
+                _ => write!(f,
+                    "Can't connect to MPD at {}: {}\nCheck MPD_HOST and MPD_PORT, or the server profile in the config.", address, source),
+            },
+            StartupError::Auth { address, message } => write!(f,
+                "MPD at {} rejected the login: {}\nSet the password with MPD_PASSWORD_FILE, MPD_PASSWORD, MPD_HOST=password@host or the server profile.", address, message),
+            StartupError::PasswordFile { path, message } => write!(f,
+                "Error reading the MPD password from {}: {}", path.display(), message),
+            StartupError::Mpd { address, source } => write!(f, "Error talking to MPD at {}: {}", address, source),
+            StartupError::NoDisplay(message) => write!(f, "No display to open the window on: {}", message),
+            StartupError::Screenshot(message) => write!(f, "Error taking screenshot: {}", message),
+        }
+    }
+}
+
+// One line about an error from MPD while the display is running, to show on screen
+pub fn player_error_message(error: &Error) -> String {
+    match error {
+        Error::Io(e) if e.kind() == io::ErrorKind::ConnectionRefused => "MPD refused the connection, retrying".to_owned(),
+        Error::Io(_) => "Lost the connection to MPD, reconnecting".to_owned(),
+        Error::Server(e) if e.code == ErrorCode::Password => "MPD rejected the password".to_owned(),
+        Error::Server(e) if e.code == ErrorCode::Permission => format!("MPD needs a password for {}", e.command),
+        e => format!("Error from MPD: {}", e),
+    }
+}
+
+// Opening a window panics without a display to put it on, so this has to be checked first
+pub fn check_display() -> Result<(), StartupError> {
+    if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+        return Err(StartupError::NoDisplay("neither DISPLAY nor WAYLAND_DISPLAY is set".to_owned()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use mpd::error::ServerError;
+    use super::*;
+
+    fn server_error(code: ErrorCode) -> Error {
+        Error::Server(ServerError {
+            code,
+            pos: 0,
+            command: "status".to_owned(),
+            detail: "nope".to_owned(),
+        })
+    }
+
+    #[test]
+    fn mpd_errors() {
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        let error = StartupError::from_mpd("localhost:6600", Error::Io(refused));
+        assert!(matches!(error, StartupError::Connection { .. }));
+        assert!(error.to_string().contains("refused"));
+        assert!(matches!(StartupError::from_mpd("localhost:6600", server_error(ErrorCode::Password)), StartupError::Auth { .. }));
+        assert!(matches!(StartupError::from_mpd("localhost:6600", server_error(ErrorCode::Permission)), StartupError::Auth { .. }));
+        assert!(matches!(StartupError::from_mpd("localhost:6600", server_error(ErrorCode::NoExist)), StartupError::Mpd { .. }));
+    }
+
+    #[test]
+    fn player_error_messages() {
+        assert_eq!(player_error_message(&server_error(ErrorCode::Password)), "MPD rejected the password");
+        assert_eq!(player_error_message(&server_error(ErrorCode::Permission)), "MPD needs a password for status");
+        assert_eq!(player_error_message(&Error::Io(io::ErrorKind::UnexpectedEof.into())), "Lost the connection to MPD, reconnecting");
+    }
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let errors = [
+            StartupError::Screenshot(String::new()),
+            StartupError::Config { path: PathBuf::new(), message: String::new() },
+            StartupError::Asset { path: PathBuf::new(), message: String::new() },
+            StartupError::Connection { address: String::new(), source: io::ErrorKind::TimedOut.into() },
+            StartupError::Auth { address: String::new(), message: String::new() },
+            StartupError::Mpd { address: String::new(), source: server_error(ErrorCode::System) },
+            StartupError::NoDisplay(String::new()),
+            StartupError::PasswordFile { path: PathBuf::new(), message: String::new() },
+        ];
+        let mut codes: Vec<i32> = errors.iter().map(StartupError::exit_code).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        // 0 is success and 2 is for usage errors
+        assert!(!codes.contains(&0) && !codes.contains(&2));
+    }
+}
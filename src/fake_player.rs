@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+use mpd::error::{Error, ErrorCode, Result, ServerError};
+use mpd::song::{Id, QueuePlace, Song};
+use mpd::status::{State, Status};
+use mpd::{Output, Subsystem};
+use crate::player::{ChangeWatcher, PlayerBackend};
+
+// In-memory player that tests script directly: fill the queue, change songs, drop the connection
+// and look at which commands the display sent
+pub struct FakeBackend {
+    pub queue: Vec<Song>,
+    // Index into the queue of the current song
+    pub current: Option<usize>,
+    pub state: State,
+    pub elapsed: Duration,
+    // -1 like MPD without a mixer
+    pub volume: i8,
+    pub random: bool,
+    pub repeat: bool,
+    // Embedded pictures by file name
+    pub pictures: HashMap<String, Vec<u8>>,
+    // Every partition has the same queue here, only the names matter
+    pub partitions: Vec<String>,
+    pub partition: String,
+    pub outputs: Vec<Output>,
+    // While false every command fails like a dropped connection
+    pub connected: bool,
+    // Every command that changed something, e.g. "next" or "setvol 40"
+    pub commands: Vec<String>,
+    watchers: Vec<Sender<Subsystem>>,
+    next_id: u32,
+}
+
+impl Default for FakeBackend {
+    fn default() -> Self {
+        FakeBackend {
+            queue: Vec::new(),
+            current: None,
+            state: State::Stop,
+            elapsed: Duration::ZERO,
+            volume: 50,
+            random: false,
+            repeat: false,
+            pictures: HashMap::new(),
+            partitions: vec!["default".to_owned()],
+            partition: "default".to_owned(),
+            outputs: vec![Output { id: 0, name: "Default".to_owned(), enabled: true }],
+            connected: true,
+            commands: Vec::new(),
+            watchers: Vec::new(),
+            next_id: 1,
+        }
+    }
+}
+
+impl FakeBackend {
+    // Adds a song to the end of the queue, returns its id
+    pub fn push(&mut self, file: &str, title: Option<&str>, artist: Option<&str>) -> Id {
+        let id = Id(self.next_id);
+        self.next_id += 1;
+        self.queue.push(Song {
+            file: file.to_owned(),
+            title: title.map(str::to_owned),
+            artist: artist.map(str::to_owned),
+            duration: Some(Duration::from_secs(200)),
+            place: Some(QueuePlace { id, pos: 0, prio: 0 }),
+            ..Song::default()
+        });
+        self.renumber();
+        id
+    }
+
+    pub fn remove(&mut self, position: usize) {
+        self.queue.remove(position);
+        self.current = match self.current {
+            Some(current) if current == position => None,
+            Some(current) if current > position => Some(current - 1),
+            current => current,
+        };
+        self.renumber();
+    }
+
+    // Starts playing the song at this position
+    pub fn play(&mut self, position: usize) {
+        self.current = Some(position);
+        self.state = State::Play;
+        self.elapsed = Duration::ZERO;
+    }
+
+    // Tells every watcher, like MPD's idle
+    pub fn changed(&mut self, subsystem: Subsystem) {
+        self.watchers.retain(|watcher| watcher.send(subsystem).is_ok());
+    }
+
+    pub fn disconnect(&mut self) {
+        self.connected = false;
+    }
+
+    pub fn reconnect(&mut self) {
+        self.connected = true;
+    }
+
+    fn renumber(&mut self) {
+        for (position, song) in self.queue.iter_mut().enumerate() {
+            if let Some(place) = &mut song.place {
+                place.pos = position as u32;
+            }
+        }
+    }
+
+    fn check_connected(&self) -> Result<()> {
+        match self.connected {
+            true => Ok(()),
+            false => Err(Error::Io(io::Error::new(io::ErrorKind::ConnectionReset, "fake player disconnected"))),
+        }
+    }
+
+    fn command(&mut self, command: String) -> Result<()> {
+        self.check_connected()?;
+        self.commands.push(command);
+        Ok(())
+    }
+
+    fn next_position(&self) -> Option<usize> {
+        let current = self.current?;
+        match current + 1 < self.queue.len() {
+            true => Some(current + 1),
+            false if self.repeat && !self.queue.is_empty() => Some(0),
+            false => None,
+        }
+    }
+}
+
+impl PlayerBackend for FakeBackend {
+    fn status(&mut self) -> Result<Status> {
+        self.check_connected()?;
+        let place = |position: Option<usize>| position.and_then(|position| self.queue.get(position)).and_then(|song| song.place);
+        let duration = self.current.and_then(|current| self.queue.get(current)).and_then(|song| song.duration);
+        Ok(Status {
+            volume: self.volume,
+            repeat: self.repeat,
+            random: self.random,
+            queue_len: self.queue.len() as u32,
+            state: self.state,
+            song: place(self.current),
+            nextsong: place(self.next_position()),
+            time: duration.map(|duration| (self.elapsed, duration)),
+            elapsed: duration.map(|_| self.elapsed),
+            duration,
+            ..Status::default()
+        })
+    }
+
+    fn song(&mut self, id: Id) -> Result<Option<Song>> {
+        self.check_connected()?;
+        Ok(self.queue.iter().find(|song| song.place.is_some_and(|place| place.id == id)).cloned())
+    }
+
+    fn current_song(&mut self) -> Result<Option<Song>> {
+        self.check_connected()?;
+        Ok(self.current.and_then(|current| self.queue.get(current)).cloned())
+    }
+
+    fn queue(&mut self) -> Result<Vec<Song>> {
+        self.check_connected()?;
+        Ok(self.queue.clone())
+    }
+
+    fn toggle_pause(&mut self) -> Result<()> {
+        self.command("pause".to_owned())?;
+        self.state = match self.state {
+            State::Play => State::Pause,
+            State::Pause => State::Play,
+            State::Stop => State::Stop,
+        };
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.command("next".to_owned())?;
+        match self.next_position() {
+            Some(position) => self.play(position),
+            None => {
+                self.current = None;
+                self.state = State::Stop;
+            },
+        }
+        Ok(())
+    }
+
+    fn prev(&mut self) -> Result<()> {
+        self.command("previous".to_owned())?;
+        if let Some(current) = self.current {
+            self.play(current.saturating_sub(1));
+        }
+        Ok(())
+    }
+
+    fn play_position(&mut self, position: u32) -> Result<()> {
+        self.command(format!("play {}", position))?;
+        self.play(position as usize);
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: i8) -> Result<()> {
+        self.command(format!("setvol {}", volume))?;
+        self.volume = volume;
+        Ok(())
+    }
+
+    fn set_random(&mut self, random: bool) -> Result<()> {
+        self.command(format!("random {}", random as u8))?;
+        self.random = random;
+        Ok(())
+    }
+
+    fn set_repeat(&mut self, repeat: bool) -> Result<()> {
+        self.command(format!("repeat {}", repeat as u8))?;
+        self.repeat = repeat;
+        Ok(())
+    }
+
+    fn read_picture(&mut self, file: &str) -> Result<Option<Vec<u8>>> {
+        self.check_connected()?;
+        Ok(self.pictures.get(file).cloned())
+    }
+
+    fn partitions(&mut self) -> Result<Vec<String>> {
+        self.check_connected()?;
+        Ok(self.partitions.clone())
+    }
+
+    fn switch_partition(&mut self, name: &str) -> Result<()> {
+        self.check_connected()?;
+        if !self.partitions.iter().any(|partition| partition == name) {
+            return Err(Error::Server(ServerError {
+                code: ErrorCode::NoExist,
+                pos: 0,
+                command: "partition".to_owned(),
+                detail: "partition does not exist".to_owned(),
+            }));
+        }
+        self.command(format!("partition {}", name))?;
+        self.partition = name.to_owned();
+        Ok(())
+    }
+
+    fn outputs(&mut self) -> Result<Vec<Output>> {
+        self.check_connected()?;
+        Ok(self.outputs.clone())
+    }
+
+    fn set_output(&mut self, id: u32, enabled: bool) -> Result<()> {
+        let command = if enabled { "enableoutput" } else { "disableoutput" };
+        self.check_connected()?;
+        let position = match self.outputs.iter().position(|output| output.id == id) {
+            Some(position) => position,
+            None => return Err(Error::Server(ServerError {
+                code: ErrorCode::NoExist,
+                pos: 0,
+                command: command.to_owned(),
+                detail: "No such audio output".to_owned(),
+            })),
+        };
+        self.command(format!("{} {}", command, id))?;
+        self.outputs[position].enabled = enabled;
+        self.changed(Subsystem::Output);
+        Ok(())
+    }
+
+    // Every change is reported, whichever subsystems were asked for
+    fn watch(&mut self, _subsystems: &[Subsystem]) -> Result<ChangeWatcher> {
+        self.check_connected()?;
+        let (sender, changes) = mpsc::channel();
+        self.watchers.push(sender);
+        Ok(ChangeWatcher::new(changes))
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+use speedy2d::dimen::Vec2;
+use crate::config::GestureConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    Tap(Vec2),
+    LongPress(Vec2),
+    SwipeLeft,
+    SwipeRight,
+    SwipeUp,
+    SwipeDown,
+}
+
+struct Press {
+    start: Vec2,
+    at: Instant,
+    // A long press fires while still held, the release afterwards is ignored
+    long_pressed: bool,
+}
+
+// Turns touches (which arrive as mouse events) into gestures
+pub struct GestureTracker {
+    config: GestureConfig,
+    press: Option<Press>,
+}
+
+impl GestureTracker {
+    pub fn new(config: GestureConfig) -> GestureTracker {
+        GestureTracker {
+            config,
+            press: None,
+        }
+    }
+
+    pub fn set_config(&mut self, config: GestureConfig) {
+        self.config = config;
+    }
+
+    pub fn press(&mut self, position: Vec2) {
+        self.press = Some(Press {
+            start: position,
+            at: Instant::now(),
+            long_pressed: false,
+        });
+    }
+
+    // Called every frame, returns a long press once it has been held long enough without moving
+    pub fn poll(&mut self, position: Vec2, viewport: Vec2) -> Option<Gesture> {
+        let tap_distance = self.config.tap_distance * viewport.x.min(viewport.y);
+        let long_press = Duration::from_millis(self.config.long_press_ms);
+        let press = self.press.as_mut()?;
+        if press.long_pressed || press.at.elapsed() < long_press || (position - press.start).magnitude() > tap_distance {
+            return None;
+        }
+        press.long_pressed = true;
+        Some(Gesture::LongPress(press.start))
+    }
+
+    pub fn release(&mut self, position: Vec2, viewport: Vec2) -> Option<Gesture> {
+        let press = self.press.take()?;
+        if press.long_pressed {
+            return None;
+        }
+        let min_side = viewport.x.min(viewport.y);
+        let moved = position - press.start;
+        if moved.magnitude() <= self.config.tap_distance * min_side {
+            return Some(Gesture::Tap(press.start));
+        }
+        if moved.magnitude() < self.config.swipe_distance * min_side {
+            return None;
+        }
+        // Whichever direction it mostly moved in
+        Some(match (moved.x.abs() > moved.y.abs(), moved.x > 0.0, moved.y > 0.0) {
+            (true, true, _) => Gesture::SwipeRight,
+            (true, false, _) => Gesture::SwipeLeft,
+            (false, _, true) => Gesture::SwipeDown,
+            (false, _, false) => Gesture::SwipeUp,
+        })
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::{Duration, Instant};
+use speedy2d::Graphics2D;
+use speedy2d::color::Color;
+use speedy2d::dimen::Vec2;
+use speedy2d::font::{Font, TextLayout, TextOptions};
+use speedy2d::image::ImageHandle;
+use speedy2d::shape::Rectangle;
+use mpd::status::{State, Status};
+use log::info;
+use crate::clock;
+use crate::config::{IdleConfig, IdleMode};
+
+// Screensaver shown after playback has been stopped or paused for a while, so static elements
+// don't burn into OLED panels
+pub struct IdleScreen {
+    config: IdleConfig,
+    idle_since: Option<Instant>,
+    active: bool,
+}
+
+impl IdleScreen {
+    pub fn new(config: IdleConfig) -> IdleScreen {
+        IdleScreen {
+            config,
+            idle_since: None,
+            active: false,
+        }
+    }
+
+    pub fn set_config(&mut self, config: IdleConfig) {
+        self.config = config;
+    }
+
+    // Called every frame, returns whether the screensaver should be drawn instead of the display
+    pub fn update(&mut self, status: &Status) -> bool {
+        let idle = status.state != State::Play || status.queue_len == 0;
+        if !idle {
+            self.idle_since = None;
+        } else if self.idle_since.is_none() {
+            self.idle_since = Some(Instant::now());
+        }
+        let active = match (self.idle_since, self.config.after_minutes) {
+            (Some(since), minutes) if minutes > 0.0 => since.elapsed() >= Duration::from_secs_f32(minutes * 60.0),
+            _ => false,
+        };
+        if active != self.active {
+            info!("{} idle screen", if active { "Showing" } else { "Leaving" });
+            self.active = active;
+        }
+        active
+    }
+
+    // Mouse and keyboard input restarts the idle timer, returns whether the idle screen was showing
+    pub fn wake(&mut self) -> bool {
+        if self.idle_since.is_some() {
+            self.idle_since = Some(Instant::now());
+        }
+        let was_active = self.active;
+        self.active = false;
+        was_active
+    }
+
+    pub fn draw(&self, graphics: &mut Graphics2D, viewport: Vec2, font: &Font, album: Option<&ImageHandle>, color: Color) {
+        graphics.clear_screen(Color::BLACK);
+        let elapsed = match self.idle_since {
+            Some(since) => since.elapsed().as_secs_f32(),
+            None => 0.0,
+        };
+        let min_side = viewport.x.min(viewport.y);
+        match self.config.mode {
+            IdleMode::Blank => {},
+            IdleMode::Clock => {
+                let text = font.layout_text(&clock::format_now(&self.config.clock_format), min_side * 0.15, TextOptions::new());
+                let position = self.drift(viewport, text.size(), elapsed);
+                graphics.draw_text(position, color, &text);
+            },
+            IdleMode::Art => {
+                if let Some(handle) = album {
+                    let size = Vec2::new(min_side * 0.3, min_side * 0.3);
+                    let position = self.drift(viewport, size, elapsed);
+                    graphics.draw_rectangle_image_tinted(Rectangle::new(position, position + size), color, handle);
+                }
+            },
+        }
+    }
+
+    // Top left corner of an element of `size` bouncing slowly between the edges of the screen.
+    // Horizontal and vertical speeds differ so it doesn't keep tracing the same path.
+    fn drift(&self, viewport: Vec2, size: Vec2, elapsed: f32) -> Vec2 {
+        let speed = self.config.drift_speed * viewport.x.min(viewport.y);
+        let bounce = |range: f32, distance: f32| {
+            if range <= 0.0 {
+                return 0.0;
+            }
+            let position = distance % (range * 2.0);
+            if position > range { range * 2.0 - position } else { position }
+        };
+        Vec2::new(
+            bounce(viewport.x - size.x, elapsed * speed),
+            bounce(viewport.y - size.y, elapsed * speed * 0.71),
+        )
+    }
+}
@@ -0,0 +1,348 @@
+use speedy2d::dimen::Vec2;
+use speedy2d::shape::Rectangle;
+use crate::config::VisualizerPlacement;
+use crate::theme::{Align, Arrangement};
+use crate::transport::{self, TransportButton};
+
+// Sizes of everything that has been laid out already, the layout is built around them
+pub struct TextSizes {
+    // Pixel size of the logo image, None if there is no logo
+    pub watermark: Option<Vec2>,
+    pub playing_from: Vec2,
+    pub queue: Vec2,
+    pub title: Vec2,
+    pub artist: Vec2,
+    // The "Up next:" label and the song underneath it
+    pub up_next: Vec2,
+    pub next_song: Vec2,
+    pub elapsed: Vec2,
+    pub duration: Vec2,
+}
+
+// What's turned on, for elements that aren't only up to the theme
+pub struct LayoutState {
+    pub transport_buttons: bool,
+    // None without a visualizer
+    pub visualizer: Option<VisualizerPlacement>,
+}
+
+pub struct HeaderLayout {
+    // The whole header, logo and text
+    pub rect: Rectangle,
+    pub watermark: Option<Rectangle>,
+    pub playing_from: Vec2,
+    pub queue: Vec2,
+}
+
+pub struct UpNextLayout {
+    // The card including its bar, which runs off the edge of the screen for a card on the right
+    pub rect: Rectangle,
+    pub label: Vec2,
+    pub next_song: Vec2,
+    // The bar when it's full, it shrinks towards its right end as the song ends
+    pub bar: Rectangle,
+}
+
+// Where every element goes for one frame. Drawing and hit-testing both go through this, so what's
+// on screen and what reacts to the mouse always agree.
+pub struct Layout {
+    pub header: Option<HeaderLayout>,
+    pub album: Option<Rectangle>,
+    // Title and artist together
+    pub title_block: Rectangle,
+    pub title: Vec2,
+    pub artist: Vec2,
+    pub visualizer: Option<Rectangle>,
+    pub progress: Rectangle,
+    // Horizontal band around the progress bar that highlights it while hovered
+    pub progress_hover: Rectangle,
+    pub elapsed: Vec2,
+    pub duration: Vec2,
+    // The progress bar with the times on either side of it
+    pub progress_with_times: Rectangle,
+    pub up_next: Option<UpNextLayout>,
+    pub volume: Option<Rectangle>,
+    // Empty when the buttons are turned off
+    pub transport: Vec<(TransportButton, Rectangle)>,
+}
+
+impl Layout {
+    pub fn compute(theme: &Arrangement, viewport: Vec2, sizes: &TextSizes, state: &LayoutState) -> Layout {
+        let header = match theme.header.visible {
+            true => Some(header(theme, viewport, sizes)),
+            false => None,
+        };
+        let album = match theme.album.visible {
+            true => {
+                let side = theme.album.size.resolve(viewport);
+                let size = Vec2::new(side, side);
+                let top_left = theme.album.position.top_left(viewport, size);
+                Some(Rectangle::new(top_left, top_left + size))
+            },
+            false => None,
+        };
+
+        let title_gap = theme.title.gap.resolve(viewport);
+        let title_block_size = Vec2::new(sizes.title.x.max(sizes.artist.x), sizes.title.y + title_gap + sizes.artist.y);
+        let title_block_position = theme.title.position.top_left(viewport, title_block_size);
+        let title_block = Rectangle::new(title_block_position, title_block_position + title_block_size);
+        let aligned_x = |width: f32| match theme.title.align {
+            Align::Left => title_block.left(),
+            Align::Center => title_block.left() + (title_block.width() - width) / 2.0,
+            Align::Right => title_block.right() - width,
+        };
+        let title = Vec2::new(aligned_x(sizes.title.x), title_block.top());
+        let artist = Vec2::new(aligned_x(sizes.artist.x), title_block.top() + sizes.title.y + title_gap);
+        let visualizer = state.visualizer.map(|placement| match placement {
+            VisualizerPlacement::Behind => title_block.clone(),
+            VisualizerPlacement::Beneath => {
+                let top = title_block.bottom() + title_gap;
+                Rectangle::from_tuples((title_block.left(), top), (title_block.right(), top + sizes.title.y * 0.5))
+            },
+        });
+
+        let progress_size = Vec2::new(theme.progress.width.resolve(viewport), theme.progress.height.resolve(viewport));
+        let progress_position = theme.progress.position.top_left(viewport, progress_size);
+        let progress = Rectangle::new(progress_position, progress_position + progress_size);
+        let bar_height = progress.height();
+        let bar_middle = progress.top() + bar_height * 0.5;
+        let margins = viewport.y * 0.02;
+        let progress_hover = Rectangle::from_tuples((0.0, bar_middle - margins), (viewport.x, bar_middle + margins));
+        let times_y = progress.top() - sizes.elapsed.y / 3.1;
+        let elapsed = Vec2::new(progress.left() - sizes.elapsed.x - bar_height * 1.5, times_y);
+        let duration = Vec2::new(progress.right() + bar_height * 1.5, times_y);
+        let progress_with_times = Rectangle::from_tuples(
+            (elapsed.x, times_y.min(progress.top())),
+            (duration.x + sizes.duration.x, (times_y + sizes.elapsed.y.max(sizes.duration.y)).max(progress.bottom())),
+        );
+
+        let up_next = match theme.up_next.visible {
+            true => Some(up_next(theme, viewport, sizes, bar_height)),
+            false => None,
+        };
+        let volume = match theme.volume.visible {
+            true => {
+                let size = Vec2::new(theme.volume.width.resolve(viewport), theme.volume.height.resolve(viewport));
+                let top_left = theme.volume.position.top_left(viewport, size);
+                Some(Rectangle::new(top_left, top_left + size))
+            },
+            false => None,
+        };
+        let transport = match state.transport_buttons && theme.transport.visible {
+            true => transport::button_rects(&theme.transport, viewport),
+            false => Vec::new(),
+        };
+
+        Layout {
+            header,
+            album,
+            title_block,
+            title,
+            artist,
+            visualizer,
+            progress,
+            progress_hover,
+            elapsed,
+            duration,
+            progress_with_times,
+            up_next,
+            volume,
+            transport,
+        }
+    }
+
+    // Areas the clock has to stay clear of. The up next card is reserved even while it's hidden,
+    // so the clock doesn't jump around at 90%.
+    pub fn clock_avoid(&self) -> Vec<Rectangle> {
+        let mut avoid = vec![self.title_block.clone(), self.progress_with_times.clone()];
+        avoid.extend(self.header.iter().map(|header| header.rect.clone()));
+        avoid.extend(self.album.iter().cloned());
+        avoid.extend(self.up_next.iter().map(|up_next| up_next.rect.clone()));
+        avoid
+    }
+}
+
+// Watermark logo with the text next to it
+fn header(theme: &Arrangement, viewport: Vec2, sizes: &TextSizes) -> HeaderLayout {
+    let logo_height = theme.header.logo_height.resolve(viewport);
+    let watermark_size = match sizes.watermark {
+        Some(size) => size * (logo_height / size.y),
+        None => Vec2::ZERO,
+    };
+    let text_x_offset = watermark_size.x + watermark_size.y / 4.0;
+    let size = Vec2::new(
+        text_x_offset + sizes.playing_from.x.max(sizes.queue.x),
+        watermark_size.y.max(sizes.playing_from.y * 2.0 + sizes.queue.y),
+    );
+    let position = theme.header.position.top_left(viewport, size);
+    let playing_from = Vec2::new(position.x + text_x_offset, position.y + sizes.playing_from.y);
+    HeaderLayout {
+        rect: Rectangle::new(position, position + size),
+        watermark: sizes.watermark.map(|_| Rectangle::new(position, position + watermark_size)),
+        playing_from,
+        queue: playing_from + Vec2::new(0.0, sizes.queue.y),
+    }
+}
+
+fn up_next(theme: &Arrangement, viewport: Vec2, sizes: &TextSizes, bar_height: f32) -> UpNextLayout {
+    let font_size = theme.up_next.size.resolve(viewport);
+    let size = Vec2::new(
+        sizes.up_next.x.max(sizes.next_song.x),
+        sizes.up_next.y + sizes.next_song.y + font_size / 2.0 + bar_height,
+    );
+    let position = theme.up_next.position.top_left(viewport, size);
+    // A card on the right runs its bar off the edge of the screen
+    let bar_end = match theme.up_next.position.anchor.is_right() {
+        true => viewport.x + 5.0,
+        false => position.x + size.x,
+    };
+    let bar_top = position.y + sizes.up_next.y + sizes.next_song.y + font_size / 2.0;
+    UpNextLayout {
+        rect: Rectangle::new(position, Vec2::new(bar_end, position.y + size.y)),
+        label: position,
+        next_song: position + Vec2::new(0.0, sizes.up_next.y),
+        bar: Rectangle::from_tuples((position.x, bar_top), (bar_end, bar_top + bar_height)),
+    }
+}
+
+pub fn overlaps(a: &Rectangle, b: &Rectangle) -> bool {
+    a.left() < b.right() && b.left() < a.right() && a.top() < b.bottom() && b.top() < a.bottom()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme;
+
+    const RESOLUTIONS: [(f32, f32); 11] = [
+        (3840.0, 2160.0),
+        (1920.0, 1080.0),
+        (1280.0, 720.0),
+        (1024.0, 600.0),
+        (800.0, 480.0),
+        (1024.0, 768.0),
+        (1280.0, 1080.0),
+        (1080.0, 1080.0),
+        (1080.0, 1920.0),
+        (720.0, 1280.0),
+        (480.0, 800.0),
+    ];
+
+    // Roughly what the fonts lay out: half the font size per character, 1.2 lines high
+    fn text(text: &str, font_size: f32) -> Vec2 {
+        Vec2::new(text.chars().count() as f32 * font_size * 0.5, font_size * 1.2)
+    }
+
+    // Shrunk to the maximum width like update_text does
+    fn shrunk(text_size: Vec2, max_width: f32) -> Vec2 {
+        match text_size.x < max_width {
+            true => text_size,
+            false => text_size * (max_width * 0.99 / text_size.x),
+        }
+    }
+
+    fn sizes(theme: &Arrangement, viewport: Vec2) -> TextSizes {
+        let max_width = theme.title.max_width.resolve(viewport);
+        let up_next_size = theme.up_next.size.resolve(viewport);
+        let bar_font_size = theme.progress.height.resolve(viewport) * 3.0;
+        TextSizes {
+            watermark: Some(Vec2::new(200.0, 200.0)),
+            playing_from: text("PLAYING FROM MPD QUEUE", theme.header.playing_from_size.resolve(viewport)),
+            queue: text("12 tracks left", theme.header.queue_size.resolve(viewport)),
+            title: shrunk(text("A Reasonably Long Song Title", theme.title.title_size.resolve(viewport)), max_width),
+            artist: shrunk(text("Some Artist", theme.title.artist_size.resolve(viewport)), max_width),
+            up_next: text("Up next:", up_next_size * 1.1),
+            next_song: text("Some Artist - Another Song", up_next_size),
+            elapsed: text("1:23", bar_font_size),
+            duration: text("4:56", bar_font_size),
+        }
+    }
+
+    // Every element that has to be seen on its own, by name
+    fn elements(layout: &Layout) -> Vec<(String, Rectangle)> {
+        let mut elements = vec![
+            ("title".to_owned(), layout.title_block.clone()),
+            ("progress".to_owned(), layout.progress_with_times.clone()),
+        ];
+        elements.extend(layout.header.iter().map(|header| ("header".to_owned(), header.rect.clone())));
+        elements.extend(layout.album.iter().map(|album| ("album".to_owned(), album.clone())));
+        elements.extend(layout.up_next.iter().map(|up_next| ("up next".to_owned(), up_next.rect.clone())));
+        elements.extend(layout.volume.iter().map(|volume| ("volume".to_owned(), volume.clone())));
+        elements.extend(layout.transport.iter().map(|(button, rect)| (format!("{:?} button", button), rect.clone())));
+        elements
+    }
+
+    #[test]
+    fn built_in_themes_dont_overlap() {
+        let state = LayoutState {
+            transport_buttons: true,
+            visualizer: None,
+        };
+        for theme in theme::builtin_themes() {
+            for (width, height) in RESOLUTIONS {
+                let viewport = Vec2::new(width, height);
+                let arrangement = theme.arrangement(viewport);
+                let sizes = sizes(arrangement, viewport);
+                let layout = Layout::compute(arrangement, viewport, &sizes, &state);
+                let elements = elements(&layout);
+                for (i, (name, rect)) in elements.iter().enumerate() {
+                    for (other_name, other) in &elements[i + 1..] {
+                        assert!(!overlaps(rect, other), "{} and {} overlap in {} at {}x{}", name, other_name, theme.name, width, height);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn built_in_themes_stay_on_screen() {
+        let state = LayoutState {
+            transport_buttons: true,
+            visualizer: None,
+        };
+        for theme in theme::builtin_themes() {
+            for (width, height) in RESOLUTIONS {
+                let viewport = Vec2::new(width, height);
+                let screen = Rectangle::from_tuples((0.0, 0.0), (width, height));
+                let arrangement = theme.arrangement(viewport);
+                let sizes = sizes(arrangement, viewport);
+                let layout = Layout::compute(arrangement, viewport, &sizes, &state);
+                for (name, rect) in elements(&layout) {
+                    // The up next bar runs off the right edge on purpose
+                    let rect = match name.as_str() {
+                        "up next" => Rectangle::from_tuples((rect.left(), rect.top()), (rect.right().min(width), rect.bottom())),
+                        _ => rect,
+                    };
+                    assert!(screen.contains(*rect.top_left()) && screen.contains(*rect.bottom_right() - Vec2::new(0.01, 0.01)),
+                        "{} is off screen in {} at {}x{}", name, theme.name, width, height);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn visualizer_placement() {
+        let theme = &theme::builtin_themes()[0];
+        let viewport = Vec2::new(1920.0, 1080.0);
+        let arrangement = theme.arrangement(viewport);
+        let sizes = sizes(arrangement, viewport);
+        let behind = Layout::compute(arrangement, viewport, &sizes, &LayoutState { transport_buttons: false, visualizer: Some(VisualizerPlacement::Behind) });
+        assert_eq!(behind.visualizer, Some(behind.title_block.clone()));
+        assert!(behind.transport.is_empty());
+        let beneath = Layout::compute(arrangement, viewport, &sizes, &LayoutState { transport_buttons: false, visualizer: Some(VisualizerPlacement::Beneath) });
+        let visualizer = beneath.visualizer.unwrap();
+        assert!(visualizer.top() > beneath.title_block.bottom());
+        assert_eq!(visualizer.left(), beneath.title_block.left());
+    }
+
+    #[test]
+    fn progress_hover_band() {
+        let theme = &theme::builtin_themes()[0];
+        let viewport = Vec2::new(1920.0, 1080.0);
+        let arrangement = theme.arrangement(viewport);
+        let layout = Layout::compute(arrangement, viewport, &sizes(arrangement, viewport), &LayoutState { transport_buttons: false, visualizer: None });
+        let middle = layout.progress.top() + layout.progress.height() / 2.0;
+        assert!(layout.progress_hover.contains(Vec2::new(5.0, middle + 20.0)));
+        assert!(!layout.progress_hover.contains(Vec2::new(5.0, middle + 25.0)));
+    }
+}
@@ -0,0 +1,4 @@
+// The parts of the display that don't need a window, so the integration tests in tests/ can use
+// them too. The fake player also stands in for MPD when rendering screenshots from a fixture.
+pub mod fake_player;
+pub mod player;
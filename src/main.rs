@@ -1,36 +1,89 @@
+mod artist;
+mod assets;
+mod background;
+mod cli;
+mod clock;
+mod config;
+mod error;
+mod gesture;
+mod idle;
+mod layout;
+mod mpd_host;
+mod notification;
+mod overlay;
+mod paths;
+mod screenshot;
+#[cfg(egl)]
+mod egl;
+#[cfg(test)]
+mod test_dir;
+mod theme;
+mod visualizer;
+mod transport;
+mod volume;
+mod watcher;
+
 use std::path::Path;
-use std::cmp::{min, max};
-use std::io::Cursor;
-use std::process::Command;
+use std::sync::Arc;
 use speedy2d::Window;
 use speedy2d::color::Color;
-use speedy2d::window::{WindowHelper, WindowFullscreenMode, WindowStartupInfo, VirtualKeyCode, KeyScancode, MouseButton};
+use speedy2d::window::{WindowHelper, WindowFullscreenMode, WindowStartupInfo, VirtualKeyCode, KeyScancode, MouseButton, MouseScrollDistance};
 use speedy2d::Graphics2D;
 use speedy2d::dimen::{UVec2, Vec2};
 use speedy2d::shape::{Rectangle, RoundedRectangle};
 use speedy2d::font::{Font, FormattedTextBlock, TextLayout, TextOptions};
-use speedy2d::image::{ImageHandle, ImageFileFormat, ImageSmoothingMode};
-use mpd::Client;
-use mpd::status::Status;
-use mpd::song::Song;
-use log::info;
+use speedy2d::image::{ImageHandle, ImageSmoothingMode, ImageDataType};
+use mpd::Subsystem;
+use mpd::status::State;
+use log::{info, warn};
+use image::RgbaImage;
+use artist::ArtistMatcher;
+use background::{ArtistBackground, ArtistImages};
+use cli::Args;
+use config::{Config, ServerConfig, VisualizerPlacement};
+use error::StartupError;
+use gesture::{Gesture, GestureTracker};
+use idle::IdleScreen;
+use layout::{Layout, LayoutState, TextSizes};
+use mpd_host::MpdHost;
+use notification::Notifier;
+use mpd_display_rs::player::{ChangeWatcher, MpdBackend, NowPlaying, PlayerBackend};
+use overlay::{Overlay, QueueEntry, SettingsItem, SETTINGS_ITEMS};
+use screenshot::Fixture;
+use theme::{AlbumShape, Arrangement, Theme};
+use visualizer::Visualizer;
+use transport::{Transport, TransportButton};
+use volume::VolumeSlider;
+use watcher::{Change, FileWatcher};
+
+// Picked in an overlay and done on the next frame, where the images can be rebuilt
+enum Switch {
+    Server(String),
+    Partition(String),
+}
 
 struct MyWindowHandler {
     width: u32,
     height: u32,
     fullscreen: bool,
     bar_hover: bool,
+    mouse_position: Vec2,
     cursor_visible: bool,
     show_debug_window: bool,
     startup: bool,
 
 
-    mpd_client: Client,
-    mpd_status: Status,
-    current_song: Option<Song>,
-    current_song_id: u32,
+    player: Box<dyn PlayerBackend>,
+    now_playing: NowPlaying,
+    // Name of the server profile in use, None when MPD_HOST is
+    server: Option<String>,
+    // None for the default partition
+    partition: Option<String>,
+    switch_to: Option<Switch>,
+    // Why the last refresh failed, shown on screen until MPD responds again
+    player_error: Option<String>,
+    text_player_error: Option<FormattedTextBlock>,
     queue_len: u32,
-    next_song: Option<Song>,
 
     font_light: Font,
     font_bold: Font,
@@ -49,10 +102,30 @@ struct MyWindowHandler {
     color_background: Color,
     color_accent: Color,
 
-    image_background: Option<ImageHandle>,
+    background: Option<ArtistBackground>,
     image_watermark: Option<ImageHandle>,
     image_album: Option<ImageHandle>,
     backup_album_image: Option<ImageHandle>,
+    // Decoded pixels of the current album art, shared with the notifier
+    album_art: Option<Arc<RgbaImage>>,
+
+    themes: Vec<Theme>,
+    theme_index: usize,
+
+    config: Config,
+    artist_matcher: ArtistMatcher,
+    notifier: Notifier,
+    watcher: Option<FileWatcher>,
+    idle: IdleScreen,
+    visualizer: Option<Visualizer>,
+    volume_slider: VolumeSlider,
+    transport: Transport,
+    gestures: GestureTracker,
+    overlay: Option<Overlay>,
+    // Keeps the outputs overlay up to date while it's open
+    output_watcher: Option<ChangeWatcher>,
+    // Where everything went in the last frame, for hit-testing
+    layout: Option<Layout>,
 }
 impl egui_speedy2d::WindowHandler for MyWindowHandler {
 
@@ -85,190 +158,35 @@ impl egui_speedy2d::WindowHandler for MyWindowHandler {
         }
         graphics.clear_screen(self.color_background);
 
-        self.update_mpd(graphics);
-        if self.queue_len != self.mpd_status.queue_len {
-            self.queue_len = self.mpd_status.queue_len;
-            self.update_queue_len_text();
-        }
-        //DRAW
-        //
-        // draw BACKGROUND
-        match &self.image_background {
+        match self.switch_to.take() {
+            Some(Switch::Server(name)) => self.switch_server(graphics, &name),
+            Some(Switch::Partition(name)) => self.switch_partition(graphics, &name),
             None => {},
-            Some(handle) => {
-                let img_dims = handle.size();
-                let mut x_offset = 0.0;
-                let mut y_offset = 0.0;
-                let mut scale = self.width as f32 / img_dims.x as f32;
-                if (img_dims.y as f32 * scale) < self.height as f32 {
-                    scale = self.height as f32 / img_dims.y as f32;
-                    x_offset = (self.width as f32 / -2.0) + (img_dims.x as f32 * scale / 2.0);
-                }
-                else {
-                    y_offset = (self.height as f32 / -2.0) + (img_dims.y as f32 * scale / 2.0);
-                }
-                let rect = get_scaled_image_rect(handle, scale, (-x_offset, -y_offset));
-                graphics.draw_rectangle_image_tinted(rect, self.color_background_image_tint, handle);
-            },
-        }
-        // draw WATERMARK
-        let mut watermark_image_size = match &self.image_watermark {
-            None => UVec2::new(1,1),
-            Some(handle) => handle.size().clone(),
-        };
-        let watermark_x_offset = self.width as f32 / 20.0;
-        let watermark_y_offset = self.height as f32 / 20.0;
-        let watermark_resize_scale = (self.height as f32/9.0)/watermark_image_size.y as f32;
-        let watermark_rect = match &self.image_watermark {
-            None => Rectangle::from_tuples((watermark_x_offset, watermark_y_offset), (watermark_x_offset, watermark_y_offset)),
-            Some(handle) => get_scaled_image_rect(handle, watermark_resize_scale, (watermark_x_offset, watermark_y_offset)),
-        };
-        watermark_image_size = UVec2::new(watermark_rect.width() as u32, watermark_rect.height() as u32);
-        match &self.image_watermark {
-            None => {},
-            Some(handle) => {
-                graphics.draw_rectangle_image(watermark_rect, handle);
-            }
-        };
-        // draw PLAYINGFROM
-        let mpd_text_x_offset = watermark_x_offset as f32 + watermark_image_size.x as f32 + watermark_image_size.y as f32 / 4.0;
-        let mpd_text_size = match &self.text_playingfromqueue {
-            Some(text) => text.size(),
-            None => Vec2::new(1.0,1.0),
-        };
-        let mpd_text_y_offset = watermark_y_offset as f32 + mpd_text_size.y;
-        match &self.text_playingfromqueue {
-            None => {}
-            Some(text) => {
-                graphics.draw_text((mpd_text_x_offset, mpd_text_y_offset), self.text_color_background, &text);
-            }
-        };
-        // draw QUEUE
-        let queue_text_size = match &self.text_queue {
-            Some(text) => text.size(),
-            None => Vec2::new(1.0, 1.0),
-        };
-        let queue_y_offset = mpd_text_y_offset + queue_text_size.y;
-        match &self.text_queue {
-            None => {},
-            Some(text) => {
-                graphics.draw_text((mpd_text_x_offset, queue_y_offset), self.text_color_background, &text);
-            }
-        };
-        // draw ALBUMART
-        let album_resize_value = min(self.height, self.width) as f32 / 3.0;
-        let album_y_offset = self.height as f32 / 6.0 * 5.0 - album_resize_value;
-        let album_x_offset = self.width as f32 / 16.0;
-        let album_rect = Rectangle::from_tuples((album_x_offset, album_y_offset), (album_x_offset + album_resize_value, album_y_offset + album_resize_value));
-        match &self.image_album {
-            None => {},
-            Some(handle) => {
-                graphics.draw_rectangle_image(album_rect, handle);
-            }
-        };
-        // draw TITLE
-        let title_x_offset = album_x_offset + album_resize_value * 1.1;
-        let title_y_offset = album_y_offset + album_resize_value * 0.4;
-        let mut title_height = 1.0;
-        match &self.text_title {
-            None => {},
-            Some(text) => {
-                graphics.draw_text((title_x_offset, title_y_offset), self.text_color_foreground, &text);
-                title_height = text.height();
-            },
-        };
-        // draw ARTIST
-        let artist_y_offset = title_y_offset + title_height + album_resize_value * 0.05;
-        match &self.text_artist {
-            None => {},
-            Some(text) => {
-                graphics.draw_text((title_x_offset, artist_y_offset), self.text_color_midground, &text);
-            }
-        };
-        // draw PROGRESSBAR:bar
-        let bar_background = Color::from_rgba(self.text_color_background.r(), self.text_color_background.g(), self.text_color_background.b(), 0.5);
-        let bar_width = self.width as f32 * 0.85;
-        let bar_offset_x = (self.width as f32 - bar_width) * 0.5;
-        let bar_height = self.height as f32 * 0.006;
-        let bar_offset_y = self.height as f32 * 0.9;
-        let mut bar_progress_color = self.text_color_foreground;
-        if self.bar_hover {
-            bar_progress_color = self.color_accent;
-        }
-        let bar_back_rect = RoundedRectangle::from_tuples((bar_offset_x, bar_offset_y), (bar_offset_x + bar_width, bar_offset_y + bar_height),  bar_height / 2.1);
-        graphics.draw_rounded_rectangle(bar_back_rect, bar_background);
-        let song_elapsed = match self.mpd_status.elapsed {
-            Some(duration) => {
-                duration.as_secs_f32()
-            },
-            None => {
-                0.0
-            }
-        };
-        let mut song_duration = match self.mpd_status.duration {
-            Some(duration) => {
-                duration.as_secs_f32()
-            },
-            None => {
-                1.0
-            }
-        };
-        if song_duration == 0.0 { song_duration = 1.0; }
-        let song_percentage = song_elapsed / song_duration;
-        let bar_progress_length = bar_width * song_percentage;
-        let bar_progress_rect = RoundedRectangle::from_tuples((bar_offset_x, bar_offset_y), (bar_offset_x + bar_progress_length, bar_offset_y + bar_height), bar_height / 2.1);
-        graphics.draw_rounded_rectangle(bar_progress_rect, bar_progress_color);
-        // draw PROGRESSBAR:elapsed
-        let (secs_elapsed, secs_duration) = match self.mpd_status.time {
-            Some((elapsed, duration)) => {
-                (elapsed.as_secs(), duration.as_secs())
-            },
-            None => {
-                (0, 1)
-            }
-        };
-        let mut elapsed_secs_str = (secs_elapsed % 60).to_string();
-        if secs_elapsed % 60 < 10 {
-            elapsed_secs_str = format!("0{}", secs_elapsed % 60);
         }
-        let mut duration_secs_str = (secs_duration % 60).to_string();
-        if secs_duration % 60 < 10 {
-            duration_secs_str = format!("0{}", secs_duration % 60);
+        self.update_mpd(graphics);
+        self.poll_outputs();
+        self.notifier.poll(self.fullscreen);
+        self.reload_changed_files(graphics);
+        if self.queue_len != self.now_playing.status.queue_len {
+            self.queue_len = self.now_playing.status.queue_len;
+            self.update_queue_len_text();
         }
-        let elapsed_str = format!("{}:{}", secs_elapsed / 60, elapsed_secs_str);
-        let duration_str = format!("{}:{}", secs_duration / 60, duration_secs_str);
-        let bar_fontsize = bar_height * 3.0;
-        let text_elapsed = self.font_light.layout_text(&elapsed_str, bar_fontsize, TextOptions::new());
-        let elapsed_x_offset = bar_offset_x - text_elapsed.width() - bar_height * 1.5;
-        let bar_text_y_offset = bar_offset_y - text_elapsed.height() / 3.1;
-        graphics.draw_text((elapsed_x_offset, bar_text_y_offset), self.text_color_foreground, &text_elapsed);
-        // draw PROGRESSBAR:duration
-        let text_duration = self.font_light.layout_text(&duration_str, bar_fontsize, TextOptions::new());
-        let duration_x_offset = bar_offset_x + bar_width + bar_height * 1.5;
-        graphics.draw_text((duration_x_offset, bar_text_y_offset), self.text_color_foreground, &text_duration);
-        if self.bar_hover {
-            let circle_x = bar_offset_x + bar_progress_length;
-            let circle_y = bar_offset_y + bar_height * 0.5;
-            graphics.draw_circle((circle_x, circle_y), bar_height, self.text_color_foreground);
+        let viewport = Vec2::new(self.width as f32, self.height as f32);
+        if let Some(gesture) = self.gestures.poll(self.mouse_position, viewport) {
+            self.handle_gesture(helper, gesture);
         }
-        // draw UPNEXT
-        if song_percentage >= 0.9 {
-            let next_offset_y = self.height as f32 / 20.0;
-            let next_offset_x = self.width as f32 - max(self.text_upnext.as_ref().unwrap().width() as i32, self.text_next_song.as_ref().unwrap().width() as i32) as f32 - self.width as f32 / 30.0;
-            let barratio = song_percentage * 10.0 - 9.0;
-            let baroffset = (self.width as f32 - next_offset_x)*barratio;
-            graphics.draw_text((next_offset_x, next_offset_y), self.text_color_foreground, self.text_upnext.as_ref().unwrap());
-            graphics.draw_text((next_offset_x, next_offset_y + self.text_upnext.as_ref().unwrap().height()), self.text_color_foreground, self.text_next_song.as_ref().unwrap());
-            let bar_fontsize = self.height as f32 / 30.0;
-            let barrect_y_offset = next_offset_y + self.text_upnext.as_ref().unwrap().height() + self.text_next_song.as_ref().unwrap().height() + bar_fontsize / 2.0;
-            let barrect = RoundedRectangle::from_tuples((next_offset_x + baroffset, barrect_y_offset), (self.width as f32 + 5.0, barrect_y_offset + bar_height), bar_height / 2.1);
-            graphics.draw_rounded_rectangle(barrect, self.text_color_foreground);
+        if self.idle.update(&self.now_playing.status) {
+            let album = self.image_album.as_ref().or(self.backup_album_image.as_ref());
+            self.idle.draw(graphics, viewport, &self.font_light, album, self.text_color_background);
+            helper.request_redraw();
+            return;
         }
+        self.draw_display(graphics);
 
         //GUI
         // egui window
         if self.show_debug_window {
-            egui::Window::new("Debug").show(&egui_ctx, |ui| {
+            egui::Window::new("Debug").show(egui_ctx, |ui| {
                 ui.heading("Window");
                 ui.label("(Note: will not change window geometry)");
                 ui.add(egui::Slider::new(&mut self.width, 0..=1920).text("Width"));
@@ -291,6 +209,10 @@ impl egui_speedy2d::WindowHandler for MyWindowHandler {
         _scancode: KeyScancode,
         _egui_ctx: &egui::Context,
     ) {
+        // Any key only leaves the idle screen
+        if self.idle.wake() {
+            return;
+        }
         match keycode {
             // F/F11: Toggle fullscreen
             Some(VirtualKeyCode::F) => {
@@ -308,17 +230,41 @@ impl egui_speedy2d::WindowHandler for MyWindowHandler {
                 helper.set_cursor_visible(self.cursor_visible);
             },
 
+            // T: Cycle through the layout themes
+            Some(VirtualKeyCode::T) => {
+                self.cycle_theme();
+            },
+
+            // S: Pick another server profile
+            Some(VirtualKeyCode::S) => {
+                self.open_servers();
+            },
+
+            // P: Pick another partition
+            Some(VirtualKeyCode::P) => {
+                self.open_partitions();
+            },
+
+            // O: Turn audio outputs on and off
+            Some(VirtualKeyCode::O) => {
+                self.open_outputs();
+            },
+
             // D: Toggle debug panel visibility
             Some(VirtualKeyCode::D) => {
                 self.show_debug_window = !self.show_debug_window;
             },
 
             Some(VirtualKeyCode::Space) => {
-                let _ = self.mpd_client.toggle_pause();
+                let _ = self.player.toggle_pause();
             },
 
+            // Escape closes an open overlay before quitting
             Some(VirtualKeyCode::Escape) => {
-                helper.terminate_loop();
+                match self.overlay {
+                    Some(_) => self.overlay = None,
+                    None => helper.terminate_loop(),
+                }
             },
             Some(VirtualKeyCode::Q) => {
                 helper.terminate_loop();
@@ -329,16 +275,54 @@ impl egui_speedy2d::WindowHandler for MyWindowHandler {
 
     fn on_mouse_button_down(
         &mut self,
-        _helper: &mut WindowHelper,
+        helper: &mut WindowHelper,
         button: MouseButton,
         _egui_ctx: &egui::Context,
     ) {
-        match button {
-            MouseButton::Left => {
-                let _ = self.mpd_client.toggle_pause();
-            },
-            _ => {
-            },
+        if self.idle.wake() {
+            return;
+        }
+        if button == MouseButton::Left {
+            if let Some(item) = self.press() {
+                self.apply_setting(helper, item);
+            }
+        }
+    }
+
+    fn on_mouse_button_up(
+        &mut self,
+        helper: &mut WindowHelper,
+        button: MouseButton,
+        _egui_ctx: &egui::Context,
+    ) {
+        if button == MouseButton::Left {
+            self.volume_slider.mouse_up();
+            let clicked = self.transport.mouse_up(&self.transport_rects(), self.mouse_position);
+            let result = match clicked {
+                Some(TransportButton::Previous) => self.player.prev(),
+                Some(TransportButton::PlayPause) => self.player.toggle_pause(),
+                Some(TransportButton::Next) => self.player.next(),
+                None => Ok(()),
+            };
+            if let Err(e) = result {
+                warn!("Error handling {:?} button: {}", clicked, e);
+            }
+            let viewport = Vec2::new(self.width as f32, self.height as f32);
+            if let Some(gesture) = self.gestures.release(self.mouse_position, viewport) {
+                self.handle_gesture(helper, gesture);
+            }
+        }
+    }
+
+    fn on_mouse_wheel_scroll(
+        &mut self,
+        _helper: &mut WindowHelper,
+        distance: MouseScrollDistance,
+        _egui_ctx: &egui::Context,
+    ) {
+        self.idle.wake();
+        if let Some(volume) = self.volume_slider.scroll(distance, self.now_playing.status.volume, self.config.volume.step) {
+            self.set_volume(volume);
         }
     }
 
@@ -368,18 +352,265 @@ impl egui_speedy2d::WindowHandler for MyWindowHandler {
         position: Vec2,
         _egui_ctx: &egui::Context,
     ) {
-        let bar_offset_y = self.height as f32 * 0.9;
-        let bar_height = self.height as f32 * 0.006;
-        let bar_middle = bar_offset_y - bar_height * 0.5;
-        let margins = self.height as f32 * 0.02;
-        let bar_low_bound = bar_middle + margins;
-        let bar_high_bound = bar_middle - margins;
-        if position.y < bar_low_bound && position.y > bar_high_bound {
-            self.bar_hover = true;
-        } else { self.bar_hover = false; }
+        self.idle.wake();
+        self.bar_hover = self.layout.as_ref().is_some_and(|layout| layout.progress_hover.contains(position));
+        self.mouse_position = position;
+        if let Some(volume_rect) = self.volume_rect() {
+            let viewport = Vec2::new(self.width as f32, self.height as f32);
+            if let Some(volume) = self.volume_slider.mouse_move(&volume_rect, viewport, position) {
+                self.set_volume(volume);
+            }
+        }
     }
 }
 impl MyWindowHandler {
+    fn new(config: Config, player: Box<dyn PlayerBackend>, now_playing: NowPlaying) -> MyWindowHandler {
+        MyWindowHandler {
+            width: 0,
+            height: 0,
+            fullscreen: true,
+            bar_hover: false,
+            mouse_position: Vec2::ZERO,
+            cursor_visible: true,
+            show_debug_window: false,
+            startup: true,
+
+            player,
+            queue_len: now_playing.status.queue_len,
+            now_playing,
+            server: None,
+            partition: None,
+            switch_to: None,
+            player_error: None,
+            text_player_error: None,
+
+            font_light: assets::load_font(paths::FONT_LIGHT, paths::EMBEDDED_FONT_LIGHT),
+            font_bold: assets::load_font(paths::FONT_BOLD, paths::EMBEDDED_FONT_BOLD),
+
+            text_playingfromqueue: None,
+            text_queue: None,
+            text_title: None,
+            text_artist: None,
+            text_upnext: None,
+            text_next_song: None,
+
+            text_color_background: config.colors.text_background.0,
+            text_color_foreground: config.colors.text_foreground.0,
+            text_color_midground: config.colors.text_midground.0,
+            color_background_image_tint: config.colors.background_image_tint.0,
+            color_background: config.colors.background.0,
+            color_accent: config.colors.accent.0,
+
+            background: None,
+            image_watermark: None,
+            image_album: None,
+            backup_album_image: None,
+            album_art: None,
+
+            artist_matcher: ArtistMatcher::new(&config.artists),
+            notifier: Notifier::new(config.notifications.clone()),
+            idle: IdleScreen::new(config.idle.clone()),
+            visualizer: Visualizer::new(config.visualizer.clone()),
+            volume_slider: VolumeSlider::new(),
+            transport: Transport::new(),
+            gestures: GestureTracker::new(config.gestures.clone()),
+            overlay: None,
+            output_watcher: None,
+            layout: None,
+            watcher: None,
+            themes: theme::load_themes(),
+            theme_index: 0,
+
+            config,
+        }
+    }
+
+    // Everything but the idle screen and the debug window, also used for screenshots
+    fn draw_display(&mut self, graphics: &mut Graphics2D) {
+        let viewport = Vec2::new(self.width as f32, self.height as f32);
+        //DRAW
+        //
+        // draw BACKGROUND
+        if let Some(background) = &self.background {
+            background.draw(graphics, (self.width as f32, self.height as f32), self.color_background_image_tint, &self.config.background);
+        }
+        let theme = self.arrangement();
+        let song_elapsed = match self.now_playing.status.elapsed {
+            Some(duration) => {
+                duration.as_secs_f32()
+            },
+            None => {
+                0.0
+            }
+        };
+        let mut song_duration = match self.now_playing.status.duration {
+            Some(duration) => {
+                duration.as_secs_f32()
+            },
+            None => {
+                1.0
+            }
+        };
+        if song_duration == 0.0 { song_duration = 1.0; }
+        let song_percentage = song_elapsed / song_duration;
+        let (secs_elapsed, secs_duration) = match self.now_playing.status.time {
+            Some((elapsed, duration)) => {
+                (elapsed.as_secs(), duration.as_secs())
+            },
+            None => {
+                (0, 1)
+            }
+        };
+        let mut elapsed_secs_str = (secs_elapsed % 60).to_string();
+        if secs_elapsed % 60 < 10 {
+            elapsed_secs_str = format!("0{}", secs_elapsed % 60);
+        }
+        let mut duration_secs_str = (secs_duration % 60).to_string();
+        if secs_duration % 60 < 10 {
+            duration_secs_str = format!("0{}", secs_duration % 60);
+        }
+        let elapsed_str = format!("{}:{}", secs_elapsed / 60, elapsed_secs_str);
+        let duration_str = format!("{}:{}", secs_duration / 60, duration_secs_str);
+        let bar_fontsize = theme.progress.height.resolve(viewport) * 3.0;
+        let text_elapsed = self.font_light.layout_text(&elapsed_str, bar_fontsize, TextOptions::new());
+        let text_duration = self.font_light.layout_text(&duration_str, bar_fontsize, TextOptions::new());
+        let text_size = |text: &Option<FormattedTextBlock>, missing: Vec2| match text {
+            Some(text) => text.size(),
+            None => missing,
+        };
+        let sizes = TextSizes {
+            watermark: self.image_watermark.as_ref().map(|handle| Vec2::new(handle.size().x as f32, handle.size().y as f32)),
+            playing_from: text_size(&self.text_playingfromqueue, Vec2::new(1.0, 1.0)),
+            queue: text_size(&self.text_queue, Vec2::new(1.0, 1.0)),
+            title: text_size(&self.text_title, Vec2::new(1.0, 1.0)),
+            artist: text_size(&self.text_artist, Vec2::ZERO),
+            up_next: text_size(&self.text_upnext, Vec2::ZERO),
+            next_song: text_size(&self.text_next_song, Vec2::ZERO),
+            elapsed: text_elapsed.size(),
+            duration: text_duration.size(),
+        };
+        let state = LayoutState {
+            transport_buttons: self.config.controls.transport_buttons,
+            visualizer: self.visualizer.as_ref().map(|_| self.config.visualizer.placement),
+        };
+        let layout = Layout::compute(theme, viewport, &sizes, &state);
+        // draw HEADER
+        if let Some(header) = &layout.header {
+            // draw WATERMARK
+            if let (Some(handle), Some(rect)) = (&self.image_watermark, &header.watermark) {
+                graphics.draw_rectangle_image(rect.clone(), handle);
+            }
+            // draw PLAYINGFROM
+            match &self.text_playingfromqueue {
+                None => {}
+                Some(text) => {
+                    graphics.draw_text(header.playing_from, self.text_color_background, text);
+                }
+            };
+            // draw QUEUE
+            match &self.text_queue {
+                None => {},
+                Some(text) => {
+                    graphics.draw_text(header.queue, self.text_color_background, text);
+                }
+            };
+        }
+        // draw ALBUMART
+        if let (Some(album_rect), Some(handle)) = (&layout.album, &self.image_album) {
+            match theme.album.shape {
+                AlbumShape::Square => graphics.draw_rectangle_image(album_rect.clone(), handle),
+                AlbumShape::Disc => {
+                    let angle = song_elapsed * theme.album.spin_rpm / 60.0 * std::f32::consts::TAU;
+                    draw_image_disc(graphics, album_rect, angle, handle);
+                },
+            }
+        }
+        // draw VISUALIZER
+        if let (Some(visualizer), Some(visualizer_rect)) = (&self.visualizer, &layout.visualizer) {
+            let alpha = match self.config.visualizer.placement {
+                VisualizerPlacement::Behind => 0.35,
+                VisualizerPlacement::Beneath => 1.0,
+            };
+            let color = Color::from_rgba(self.color_accent.r(), self.color_accent.g(), self.color_accent.b(), alpha);
+            visualizer.draw(graphics, visualizer_rect, color);
+        }
+        // draw TITLE
+        match &self.text_title {
+            None => {},
+            Some(text) => {
+                graphics.draw_text(layout.title, self.text_color_foreground, text);
+            },
+        };
+        // draw ARTIST
+        match &self.text_artist {
+            None => {},
+            Some(text) => {
+                graphics.draw_text(layout.artist, self.text_color_midground, text);
+            }
+        };
+        // draw PROGRESSBAR:bar
+        let bar_background = Color::from_rgba(self.text_color_background.r(), self.text_color_background.g(), self.text_color_background.b(), 0.5);
+        let bar_rect = &layout.progress;
+        let bar_width = bar_rect.width();
+        let bar_offset_x = bar_rect.left();
+        let bar_height = bar_rect.height();
+        let bar_offset_y = bar_rect.top();
+        let mut bar_progress_color = self.text_color_foreground;
+        if self.bar_hover {
+            bar_progress_color = self.color_accent;
+        }
+        let bar_back_rect = RoundedRectangle::from_tuples((bar_offset_x, bar_offset_y), (bar_offset_x + bar_width, bar_offset_y + bar_height),  bar_height / 2.1);
+        graphics.draw_rounded_rectangle(bar_back_rect, bar_background);
+        let bar_progress_length = bar_width * song_percentage;
+        let bar_progress_rect = RoundedRectangle::from_tuples((bar_offset_x, bar_offset_y), (bar_offset_x + bar_progress_length, bar_offset_y + bar_height), bar_height / 2.1);
+        graphics.draw_rounded_rectangle(bar_progress_rect, bar_progress_color);
+        // draw PROGRESSBAR:elapsed
+        graphics.draw_text(layout.elapsed, self.text_color_foreground, &text_elapsed);
+        // draw PROGRESSBAR:duration
+        graphics.draw_text(layout.duration, self.text_color_foreground, &text_duration);
+        if self.bar_hover {
+            let circle_x = bar_offset_x + bar_progress_length;
+            let circle_y = bar_offset_y + bar_height * 0.5;
+            graphics.draw_circle((circle_x, circle_y), bar_height, self.text_color_foreground);
+        }
+        // draw TRANSPORT
+        self.transport.draw(graphics, &layout.transport, self.mouse_position, self.now_playing.status.state == State::Play, self.text_color_foreground, self.color_accent);
+        // draw VOLUME
+        if let Some(volume_rect) = &layout.volume {
+            self.volume_slider.draw(graphics, volume_rect, self.now_playing.status.volume, bar_background, self.text_color_foreground, self.color_accent);
+        }
+        // draw UPNEXT
+        let up_next_texts = match (&layout.up_next, &self.text_upnext, &self.text_next_song) {
+            (Some(up_next), Some(text_upnext), Some(text_next_song)) => Some((up_next, text_upnext, text_next_song)),
+            _ => None,
+        };
+        if let Some((up_next, text_upnext, text_next_song)) = up_next_texts {
+            if song_percentage >= 0.9 {
+                let barratio = song_percentage * 10.0 - 9.0;
+                let baroffset = up_next.bar.width() * barratio;
+                graphics.draw_text(up_next.label, self.text_color_foreground, text_upnext);
+                graphics.draw_text(up_next.next_song, self.text_color_foreground, text_next_song);
+                let barrect = RoundedRectangle::from_tuples((up_next.bar.left() + baroffset, up_next.bar.top()), (up_next.bar.right(), up_next.bar.bottom()), bar_height / 2.1);
+                graphics.draw_rounded_rectangle(barrect, self.text_color_foreground);
+            }
+        }
+        // draw CLOCK
+        if self.config.clock.enabled {
+            clock::draw(graphics, &self.config.clock, viewport, &self.font_bold, &self.font_light, self.text_color_foreground, &layout.clock_avoid());
+        }
+        // draw PLAYER ERROR
+        if let Some(text) = &self.text_player_error {
+            let padding = text.height() * 0.5;
+            let top_left = Vec2::new((viewport.x - text.width()) / 2.0, viewport.y * 0.02 + padding);
+            let background = RoundedRectangle::new(top_left - Vec2::new(padding, padding), top_left + text.size() + Vec2::new(padding, padding), padding);
+            graphics.draw_rounded_rectangle(background, Color::from_rgba(0.0, 0.0, 0.0, 0.75));
+            graphics.draw_text(top_left, self.text_color_foreground, text);
+        }
+        self.layout = Some(layout);
+        // draw OVERLAY
+        self.draw_overlay(graphics);
+    }
+
     fn update_fullscreen(&mut self, helper: &mut WindowHelper) {
         let fullscreen_mode: WindowFullscreenMode = match self.fullscreen {
             true => WindowFullscreenMode::FullscreenBorderless,
@@ -388,138 +619,572 @@ impl MyWindowHandler {
         helper.set_fullscreen_mode(fullscreen_mode);
     }
 
-    // Runs every time the window is resized and every time the song changes
-    fn update_text(&mut self) {
-        let (title, artist) = match &self.current_song {
-            Some(song) => (song.title.clone().get_or_insert(song.file.clone()).to_owned(), song.artist.clone().get_or_insert("".to_owned()).to_owned()),
-            None => ("Nothing playing".to_owned(), "".to_owned()),
+    fn theme(&self) -> &Theme {
+        &self.themes[self.theme_index]
+    }
+
+    // The theme's arrangement for the current window shape
+    fn arrangement(&self) -> &Arrangement {
+        self.theme().arrangement(Vec2::new(self.width as f32, self.height as f32))
+    }
+
+    // None while the slider can't be used, because it's hidden or MPD has no mixer
+    fn volume_rect(&self) -> Option<Rectangle> {
+        if self.now_playing.status.volume < 0 {
+            return None;
+        }
+        self.layout.as_ref()?.volume.clone()
+    }
+
+    fn cycle_theme(&mut self) {
+        self.theme_index = (self.theme_index + 1) % self.themes.len();
+        info!("Switched to theme {}", self.theme().name);
+        self.update_text();
+    }
+
+    fn handle_gesture(&mut self, helper: &mut WindowHelper, gesture: Gesture) {
+        // While an overlay is open, gestures only interact with it
+        if self.overlay.is_some() {
+            match gesture {
+                Gesture::SwipeDown => self.overlay = None,
+                Gesture::Tap(position) => self.tap_overlay(helper, position),
+                _ => {},
+            }
+            return;
+        }
+        let result = match gesture {
+            Gesture::SwipeLeft => self.player.next(),
+            Gesture::SwipeRight => self.player.prev(),
+            Gesture::SwipeUp => self.open_queue(),
+            Gesture::LongPress(_) => {
+                self.overlay = Some(Overlay::Settings);
+                Ok(())
+            },
+            Gesture::Tap(_) if self.config.controls.click_to_pause => self.player.toggle_pause(),
+            Gesture::Tap(_) | Gesture::SwipeDown => Ok(()),
         };
-        self.text_playingfromqueue = Some(self.font_light.layout_text("PLAYING FROM MPD QUEUE", self.height as f32 / 42.0, TextOptions::new()));
-        self.update_queue_len_text();
-        let mut title_font_size = min(self.height, self.width) as f32 / 9.0;
-        let title_x_offset = self.width as f32 / 16.0 + min(self.height, self.width) as f32 / 3.0 * 1.1;
-        let title_available_pixels = (self.width as f32 - title_x_offset) * 0.97;
-        self.text_title = Some(self.font_bold.layout_text(&title, title_font_size, TextOptions::new()));
-        while self.text_title.as_ref().unwrap().width() >= title_available_pixels {
-            title_font_size = title_font_size * 0.95;
-            self.text_title = Some(self.font_bold.layout_text(&title, title_font_size, TextOptions::new()));
-        }
-        title_font_size = min(self.height, self.width) as f32 / 9.0;
-        self.text_artist = Some(self.font_bold.layout_text(&artist, title_font_size / 2.0, TextOptions::new()));
-        while self.text_artist.as_ref().unwrap().width() >= title_available_pixels {
-            title_font_size = title_font_size * 0.95;
-            self.text_artist = Some(self.font_bold.layout_text(&artist, title_font_size / 2.0, TextOptions::new()));
-        }
-
-        let (next_title, next_artist) = match &self.next_song {
-            Some(song) => (song.title.clone().get_or_insert(song.file.clone()).to_owned(), song.artist.clone().get_or_insert("".to_owned()).to_owned()),
-            None => ("Nothing".to_owned(), "".to_owned()),
+        if let Err(e) = result {
+            warn!("Error handling {:?} gesture: {}", gesture, e);
+        }
+    }
+
+    // Opens the queue overlay, listing the current song and everything after it
+    fn open_queue(&mut self) -> Result<(), mpd::error::Error> {
+        let current = self.now_playing.status.song.map(|place| place.pos);
+        let entries = self.player.queue()?.into_iter()
+            .filter_map(|song| {
+                let position = song.place?.pos;
+                if position < current.unwrap_or(0) {
+                    return None;
+                }
+                let label = match (&song.title, &song.artist) {
+                    (Some(title), Some(artist)) => format!("{} - {}", title, artist),
+                    (Some(title), None) => title.clone(),
+                    _ => song.file.clone(),
+                };
+                Some(QueueEntry { position, label, current: current == Some(position) })
+            })
+            .collect();
+        self.overlay = Some(Overlay::Queue(entries));
+        Ok(())
+    }
+
+    fn open_servers(&mut self) {
+        match self.config.servers.is_empty() {
+            true => info!("No server profiles in the config to switch between"),
+            false => self.overlay = Some(Overlay::Servers),
+        }
+    }
+
+    // Drops the connection to the current server and starts over with another profile
+    fn switch_server(&mut self, ctx: &mut Graphics2D, name: &str) {
+        let host = match self.config.servers.iter().find(|server| server.name == name).map(MpdHost::from_server) {
+            Some(Ok(host)) => host,
+            Some(Err(e)) => {
+                warn!("Error switching to server {}: {}", name, e);
+                self.player_error = e.to_string().lines().next().map(str::to_owned);
+                self.update_player_error_text();
+                return;
+            },
+            None => {
+                warn!("Server {} isn't in the config anymore", name);
+                return;
+            },
         };
-        let upnext_fontsize = self.height as f32 / 30.0;
-        self.text_upnext = Some(self.font_bold.layout_text("Up next:", upnext_fontsize * 1.1, TextOptions::new()));
-        let mut artist_str = next_artist.to_owned();
-        if artist_str != "".to_owned() {
-            artist_str = next_artist + " -";
+        info!("Switching to server {} at {}", name, host.address);
+        let mut player = MpdBackend::new(&host.address, host.password.as_deref());
+        let server = self.config.servers.iter().find(|server| server.name == name);
+        self.partition = self.config.partition_for(server).map(str::to_owned);
+        if let Some(partition) = &self.partition {
+            // Not connected yet, so this only remembers it
+            let _ = player.switch_partition(partition);
         }
-        self.text_next_song = Some(self.font_light.layout_text(&format!("{} {}", artist_str, next_title), upnext_fontsize, TextOptions::new()));
+        self.player = Box::new(player);
+        self.server = Some(name.to_owned());
+        self.forget_now_playing(ctx);
     }
 
-    fn init_images(&mut self, ctx: &mut Graphics2D) {
-        self.image_watermark = match ctx.create_image_from_file_path(Some(ImageFileFormat::PNG), ImageSmoothingMode::Linear, Path::new("./assets/logo.png")) {
-            Ok(handle) => Some(handle),
+    // Lists the partitions of the current server. Fails on MPD before 0.22, which has none.
+    fn open_partitions(&mut self) {
+        match self.player.partitions() {
+            Ok(partitions) => self.overlay = Some(Overlay::Partitions(partitions)),
+            Err(e) => warn!("Error listing partitions: {}", e),
+        }
+    }
+
+    // Watches for outputs changing elsewhere while the overlay is open, e.g. from another client
+    fn open_outputs(&mut self) {
+        match self.player.outputs() {
+            Ok(outputs) => self.overlay = Some(Overlay::Outputs(outputs)),
             Err(e) => {
-                println!("error initializing watermark image: {}", e);
+                warn!("Error listing outputs: {}", e);
+                return;
+            },
+        }
+        self.output_watcher = match self.player.watch(&[Subsystem::Output]) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                warn!("Error watching outputs, the list won't update by itself: {}", e);
                 None
+            },
+        };
+    }
+
+    // Stops watching once the overlay is closed, however that happened
+    fn poll_outputs(&mut self) {
+        if !matches!(self.overlay, Some(Overlay::Outputs(_))) {
+            self.output_watcher = None;
+            return;
+        }
+        let changed = match &self.output_watcher {
+            Some(watcher) => !watcher.poll().is_empty(),
+            None => false,
+        };
+        if changed {
+            self.refresh_outputs();
+        }
+    }
+
+    fn refresh_outputs(&mut self) {
+        match self.player.outputs() {
+            Ok(outputs) => self.overlay = Some(Overlay::Outputs(outputs)),
+            Err(e) => warn!("Error listing outputs: {}", e),
+        }
+    }
+
+    fn switch_partition(&mut self, ctx: &mut Graphics2D, name: &str) {
+        match self.player.switch_partition(name) {
+            Ok(()) => {
+                info!("Switched to partition {}", name);
+                self.partition = Some(name.to_owned());
+                self.forget_now_playing(ctx);
+            },
+            Err(e) => warn!("Error switching to partition {}: {}", name, e),
+        }
+    }
+
+    // After switching to another queue, so nothing of the old one is shown. The next refresh fills
+    // everything in again.
+    fn forget_now_playing(&mut self, ctx: &mut Graphics2D) {
+        self.now_playing = NowPlaying::default();
+        self.queue_len = 0;
+        self.player_error = None;
+        self.album_art = None;
+        self.image_album = self.backup_album_image.clone();
+        self.update_text();
+        self.update_images(ctx);
+    }
+
+    // A left click at the mouse position. Returns the settings row clicked, which needs the window
+    // to be applied.
+    fn press(&mut self) -> Option<SettingsItem> {
+        // Controls underneath an overlay can't be used
+        if self.overlay.is_none() {
+            if let Some(volume_rect) = self.volume_rect() {
+                let viewport = Vec2::new(self.width as f32, self.height as f32);
+                if let Some(volume) = self.volume_slider.mouse_down(&volume_rect, viewport, self.mouse_position) {
+                    self.set_volume(volume);
+                    return None;
+                }
             }
+            if self.transport.mouse_down(&self.transport_rects(), self.mouse_position) {
+                return None;
+            }
+        }
+        // Taps and swipes are only known once released
+        if self.config.gestures.enabled {
+            self.gestures.press(self.mouse_position);
+            return None;
+        }
+        // Without gestures a click on an overlay works like a tap
+        if self.overlay.is_some() {
+            return self.pick_row(self.mouse_position);
+        }
+        if self.config.controls.click_to_pause {
+            let _ = self.player.toggle_pause();
+        }
+        None
+    }
+
+    fn tap_overlay(&mut self, helper: &mut WindowHelper, position: Vec2) {
+        if let Some(item) = self.pick_row(position) {
+            self.apply_setting(helper, item);
+        }
+    }
+
+    // Tapping a row picks it, tapping anywhere else closes the overlay. Returns the settings row
+    // tapped, the caller applies it.
+    fn pick_row(&mut self, position: Vec2) -> Option<SettingsItem> {
+        let viewport = Vec2::new(self.width as f32, self.height as f32);
+        match &self.overlay {
+            Some(Overlay::Queue(entries)) => {
+                let rows = overlay::row_rects(viewport, entries.len());
+                if let Some(row) = overlay::row_at(&rows, position) {
+                    let queue_position = entries[row].position;
+                    if let Err(e) = self.player.play_position(queue_position) {
+                        warn!("Error playing queue position {}: {}", queue_position, e);
+                    }
+                }
+                self.overlay = None;
+            },
+            Some(Overlay::Settings) => {
+                let rows = overlay::row_rects(viewport, SETTINGS_ITEMS.len());
+                match overlay::row_at(&rows, position) {
+                    Some(row) => return Some(SETTINGS_ITEMS[row]),
+                    None => self.overlay = None,
+                }
+            },
+            Some(Overlay::Servers) => {
+                let rows = overlay::row_rects(viewport, self.config.servers.len());
+                if let Some(row) = overlay::row_at(&rows, position) {
+                    self.switch_to = Some(Switch::Server(self.config.servers[row].name.clone()));
+                }
+                self.overlay = None;
+            },
+            Some(Overlay::Partitions(partitions)) => {
+                let rows = overlay::row_rects(viewport, partitions.len());
+                if let Some(row) = overlay::row_at(&rows, position) {
+                    self.switch_to = Some(Switch::Partition(partitions[row].clone()));
+                }
+                self.overlay = None;
+            },
+            // Stays open after toggling, like the settings
+            Some(Overlay::Outputs(outputs)) => {
+                let rows = overlay::row_rects(viewport, outputs.len());
+                match overlay::row_at(&rows, position).map(|row| &outputs[row]) {
+                    Some(output) => {
+                        if let Err(e) = self.player.set_output(output.id, !output.enabled) {
+                            warn!("Error turning output {} {}: {}", output.name, if output.enabled { "off" } else { "on" }, e);
+                        }
+                        self.refresh_outputs();
+                    },
+                    None => self.overlay = None,
+                }
+            },
+            None => {},
+        }
+        None
+    }
+
+    // Settings stay open after being changed, so the new state can be seen
+    fn apply_setting(&mut self, helper: &mut WindowHelper, item: SettingsItem) {
+        let result = match item {
+            SettingsItem::Theme => {
+                self.cycle_theme();
+                Ok(())
+            },
+            SettingsItem::Server => {
+                self.open_servers();
+                Ok(())
+            },
+            SettingsItem::Partition => {
+                self.open_partitions();
+                Ok(())
+            },
+            SettingsItem::Outputs => {
+                self.open_outputs();
+                Ok(())
+            },
+            SettingsItem::Fullscreen => {
+                self.fullscreen = !self.fullscreen;
+                self.update_fullscreen(helper);
+                Ok(())
+            },
+            SettingsItem::Cursor => {
+                self.cursor_visible = !self.cursor_visible;
+                helper.set_cursor_visible(self.cursor_visible);
+                Ok(())
+            },
+            SettingsItem::Clock => {
+                self.config.clock.enabled = !self.config.clock.enabled;
+                Ok(())
+            },
+            SettingsItem::Random => self.player.set_random(!self.now_playing.status.random),
+            SettingsItem::Repeat => self.player.set_repeat(!self.now_playing.status.repeat),
+            SettingsItem::Close => {
+                self.overlay = None;
+                Ok(())
+            },
         };
-        self.backup_album_image = match ctx.create_image_from_file_path(Some(ImageFileFormat::PNG), ImageSmoothingMode::Linear, Path::new("./assets/art_backup")) {
-            Ok(handle) => Some(handle),
-            Err(e) => {
-                println!("error initializing default album image: {}", e);
-                None
+        if let Err(e) = result {
+            warn!("Error changing {:?} setting: {}", item, e);
+        }
+    }
+
+    fn draw_overlay(&self, graphics: &mut Graphics2D) {
+        let viewport = Vec2::new(self.width as f32, self.height as f32);
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        let (title, labels): (&str, Vec<(String, bool)>) = match &self.overlay {
+            Some(Overlay::Queue(entries)) => (
+                "Queue",
+                entries.iter().map(|entry| (entry.label.clone(), entry.current)).collect(),
+            ),
+            Some(Overlay::Settings) => (
+                "Settings",
+                SETTINGS_ITEMS.iter().map(|item| match item {
+                    SettingsItem::Theme => (format!("Theme: {}", self.theme().name), false),
+                    SettingsItem::Server => (format!("Server: {}", self.server.as_deref().unwrap_or("MPD_HOST")), false),
+                    SettingsItem::Partition => (format!("Partition: {}", self.partition.as_deref().unwrap_or("default")), false),
+                    SettingsItem::Outputs => ("Outputs".to_owned(), false),
+                    SettingsItem::Fullscreen => (format!("Fullscreen: {}", on_off(self.fullscreen)), self.fullscreen),
+                    SettingsItem::Cursor => (format!("Cursor: {}", on_off(self.cursor_visible)), self.cursor_visible),
+                    SettingsItem::Clock => (format!("Clock: {}", on_off(self.config.clock.enabled)), self.config.clock.enabled),
+                    SettingsItem::Random => (format!("Random: {}", on_off(self.now_playing.status.random)), self.now_playing.status.random),
+                    SettingsItem::Repeat => (format!("Repeat: {}", on_off(self.now_playing.status.repeat)), self.now_playing.status.repeat),
+                    SettingsItem::Close => ("Close".to_owned(), false),
+                }).collect(),
+            ),
+            Some(Overlay::Servers) => (
+                "Servers",
+                self.config.servers.iter().map(|server| (server.name.clone(), self.server.as_ref() == Some(&server.name))).collect(),
+            ),
+            Some(Overlay::Outputs(outputs)) => (
+                "Outputs",
+                outputs.iter().map(|output| (format!("{}: {}", output.name, on_off(output.enabled)), output.enabled)).collect(),
+            ),
+            Some(Overlay::Partitions(partitions)) => (
+                "Partitions",
+                partitions.iter().map(|name| (name.clone(), self.partition.as_deref().unwrap_or("default") == name)).collect(),
+            ),
+            None => return,
+        };
+        let rows = overlay::row_rects(viewport, labels.len());
+        overlay::draw(graphics, viewport, title, &rows, &labels, (&self.font_bold, &self.font_light), (self.text_color_foreground, self.color_accent));
+    }
+
+    // Empty when the buttons are turned off
+    fn transport_rects(&self) -> Vec<(TransportButton, Rectangle)> {
+        match &self.layout {
+            Some(layout) => layout.transport.clone(),
+            None => Vec::new(),
+        }
+    }
+
+    fn set_volume(&mut self, volume: i8) {
+        if volume == self.now_playing.status.volume {
+            return;
+        }
+        match self.player.set_volume(volume) {
+            // Shown right away instead of waiting for the next status update
+            Ok(()) => self.now_playing.status.volume = volume,
+            Err(e) => warn!("Error setting volume to {}: {}", volume, e),
+        }
+    }
+
+    // Picks the theme named in the config, falling back to the first (built-in) one
+    fn select_configured_theme(&mut self) {
+        self.theme_index = match self.themes.iter().position(|theme| theme.name == self.config.theme) {
+            Some(index) => index,
+            None => {
+                warn!("Theme {} not found, using {}", self.config.theme, self.themes[0].name);
+                0
             },
         };
+    }
+
+    // Runs every time the window is resized and every time the song changes
+    fn update_text(&mut self) {
+        let (title, artist) = self.now_playing.title_and_artist();
+        let viewport = Vec2::new(self.width as f32, self.height as f32);
+        let theme = self.arrangement().clone();
+        let partition = self.partition.as_deref().filter(|partition| *partition != "default");
+        let playing_from = match (&self.server, partition) {
+            (Some(name), Some(partition)) => format!("PLAYING FROM {} / {}", name, partition),
+            (Some(name), None) => format!("PLAYING FROM {}", name),
+            (None, Some(partition)) => format!("PLAYING FROM MPD PARTITION {}", partition),
+            (None, None) => "PLAYING FROM MPD QUEUE".to_owned(),
+        }.to_uppercase();
+        self.text_playingfromqueue = Some(self.font_light.layout_text(&playing_from, theme.header.playing_from_size.resolve(viewport), TextOptions::new()));
+        self.update_queue_len_text();
+        let title_available_pixels = theme.title.max_width.resolve(viewport);
+        self.text_title = Some(fit_text(&self.font_bold, &title, theme.title.title_size.resolve(viewport), title_available_pixels));
+        self.text_artist = Some(fit_text(&self.font_bold, &artist, theme.title.artist_size.resolve(viewport), title_available_pixels));
+
+        let upnext_fontsize = theme.up_next.size.resolve(viewport);
+        self.text_upnext = Some(self.font_bold.layout_text("Up next:", upnext_fontsize * 1.1, TextOptions::new()));
+        self.text_next_song = Some(self.font_light.layout_text(&self.now_playing.up_next_text(), upnext_fontsize, TextOptions::new()));
+        self.update_player_error_text();
+    }
+
+    fn update_player_error_text(&mut self) {
+        let font_size = self.width.min(self.height) as f32 * 0.03;
+        self.text_player_error = self.player_error.as_ref()
+            .map(|message| fit_text(&self.font_bold, message, font_size, self.width as f32 * 0.9));
+    }
+
+    fn init_images(&mut self, ctx: &mut Graphics2D) {
+        self.load_assets(ctx);
         self.update_images(ctx);
     }
 
-    fn update_mpd(&mut self, ctx: &mut Graphics2D) {
-        let old_song_id = self.current_song_id.clone();
-        self.mpd_status = self.mpd_client.status().unwrap();
-        (self.current_song, self.current_song_id) = match self.mpd_status.song {
-            Some(queue_place) => {
-                let song = match self.mpd_client.playlistid(queue_place.id) {
-                    Ok(s) => s,
-                    Err(e) => {println!("No song exists: {}", e); None},
-                };
-                if song == None { return; }
-                (self.mpd_client.playlistid(queue_place.id).unwrap(), queue_place.id.0)
+    // Without a logo the header is only text, without default art a placeholder is generated
+    fn load_assets(&mut self, ctx: &mut Graphics2D) {
+        self.image_watermark = assets::load_image(paths::LOGO, paths::EMBEDDED_LOGO)
+            .and_then(|logo| upload_image(ctx, &logo, "logo"));
+        let backup = match assets::load_image(paths::ART_BACKUP, paths::EMBEDDED_ART_BACKUP) {
+            Some(backup) => backup,
+            None => {
+                warn!("No default album art, using a generated placeholder");
+                assets::placeholder_cover()
             },
-            None => (None, u32::MAX),
         };
-        self.next_song = match self.mpd_status.nextsong {
-            Some(queue_place) => {
-                self.mpd_client.playlistid(queue_place.id).unwrap()
+        self.backup_album_image = upload_image(ctx, &backup, "default album art");
+    }
+
+    // Reloads whatever the file watcher saw change since the last frame
+    fn reload_changed_files(&mut self, ctx: &mut Graphics2D) {
+        let changes = match &mut self.watcher {
+            Some(watcher) => watcher.poll(),
+            None => return,
+        };
+        for change in changes {
+            match change {
+                Change::Config => {
+                    info!("Config file changed, reloading");
+                    let old_visualizer = self.config.visualizer.clone();
+                    self.config = Config::load(&paths::config_path());
+                    self.apply_colors();
+                    self.notifier.set_config(self.config.notifications.clone());
+                    self.idle.set_config(self.config.idle.clone());
+                    // A new visualizer starts another reader on the FIFO, only worth it when its
+                    // settings changed
+                    if self.config.visualizer != old_visualizer {
+                        self.visualizer = Visualizer::new(self.config.visualizer.clone());
+                    }
+                    self.gestures.set_config(self.config.gestures.clone());
+                    self.artist_matcher = ArtistMatcher::new(&self.config.artists);
+                    self.update_background(ctx);
+                    self.themes = theme::load_themes();
+                    self.select_configured_theme();
+                    self.update_text();
+                },
+                Change::Assets => {
+                    info!("Assets changed, reloading");
+                    self.load_assets(ctx);
+                    if self.now_playing.current_song.is_some() && self.album_art.is_none() {
+                        self.image_album = self.backup_album_image.clone();
+                    }
+                },
+                Change::Artists => {
+                    info!("Artist images changed, reloading background");
+                    // The alias file lives in the artist directory too
+                    self.artist_matcher = ArtistMatcher::new(&self.config.artists);
+                    self.update_background(ctx);
+                },
+            }
+        }
+    }
+
+    fn apply_colors(&mut self) {
+        let colors = &self.config.colors;
+        self.text_color_background = colors.text_background.0;
+        self.text_color_foreground = colors.text_foreground.0;
+        self.text_color_midground = colors.text_midground.0;
+        self.color_background_image_tint = colors.background_image_tint.0;
+        self.color_background = colors.background.0;
+        self.color_accent = colors.accent.0;
+    }
+
+    fn update_mpd(&mut self, ctx: &mut Graphics2D) {
+        let song_changed = match self.now_playing.refresh(self.player.as_mut()) {
+            Ok(song_changed) => {
+                if self.player_error.is_some() {
+                    info!("MPD is responding again");
+                    self.player_error = None;
+                    self.update_player_error_text();
+                }
+                song_changed
+            },
+            Err(e) => {
+                // Between reconnect attempts the backend only says it isn't connected, keep
+                // showing why the last attempt failed
+                let waiting = matches!(&e, mpd::error::Error::Io(io) if io.kind() == std::io::ErrorKind::NotConnected);
+                let message = error::player_error_message(&e);
+                if self.player_error.as_ref() != Some(&message) && !(waiting && self.player_error.is_some()) {
+                    warn!("Error updating MPD status: {}", e);
+                    self.player_error = Some(message);
+                    self.update_player_error_text();
+                }
+                false
             },
-            None => None,
         };
-        if self.current_song_id != old_song_id {
+        if song_changed {
             self.update_text();
             self.update_images(ctx);
+            self.notifier.song_changed(self.now_playing.current_song.as_ref(), self.album_art.clone());
         }
     }
 
     fn update_queue_len_text(&mut self) {
-        let contents = format!(
-            "{} tracks left",
-            self.mpd_status.queue_len
-        );
-        self.text_queue = Some(self.font_bold.layout_text(&contents, self.height as f32 / 31.0, TextOptions::new()));
+        let contents = self.now_playing.queue_text();
+        let viewport = Vec2::new(self.width as f32, self.height as f32);
+        let font_size = self.arrangement().header.queue_size.resolve(viewport);
+        self.text_queue = Some(self.font_bold.layout_text(&contents, font_size, TextOptions::new()));
+    }
+
+    fn update_background(&mut self, ctx: &mut Graphics2D) {
+        self.background = match &self.now_playing.current_song {
+            Some(song) => {
+                let images = ArtistImages::list();
+                match self.artist_matcher.song_candidates(song).iter().find_map(|name| images.find(name)) {
+                    Some(source) => ArtistBackground::load(ctx, &source),
+                    None => {
+                        info!("No background image found for {}", song.artist.as_deref().unwrap_or(&song.file));
+                        None
+                    },
+                }
+            },
+            None => None,
+        };
     }
 
     fn update_images(&mut self, ctx: &mut Graphics2D) {
-        match &self.current_song {
+        self.update_background(ctx);
+        match &self.now_playing.current_song {
             Some(song) => {
-                match &song.artist {
-                    Some(artist) => {
-                        let first_artist = artist
-                            .split(", ").next().unwrap()
-                            .split("/").next().unwrap()
-                            .split(" & ").next().unwrap()
-                            .split("; ").next().unwrap()
-                            .to_lowercase();
-                        self.image_background = match ctx.create_image_from_file_path(None, ImageSmoothingMode::Linear, Path::new(&format!("./artists/{}.jpg", first_artist))) {
-                            Ok(handle) => Some(handle),
+                self.album_art = match self.player.read_picture(&song.file) {
+                    Ok(Some(picture)) => {
+                        match image::load_from_memory(&picture) {
+                            Ok(decoded) => Some(Arc::new(decoded.into_rgba8())),
                             Err(e) => {
-                                println!("error initializing background image: {}", e);
+                                warn!("Error decoding album art of {}: {}", song.file, e);
                                 None
-                            },
-                        };
+                            }
+                        }
                     },
-                    None => {
-                        self.image_background = None;
+                    Ok(None) => None,
+                    Err(e) => {
+                        warn!("Error reading album art of {}: {}", song.file, e);
+                        None
                     },
                 };
-                self.image_album = match Command::new("mpc")
-                    .arg("readpicture")
-                    .arg(song.file.clone())
-                    .output() {
-                        Ok(output) => {
-                            match ctx.create_image_from_file_bytes(None, ImageSmoothingMode::Linear, Cursor::new(output.stdout)) {
-                                Ok(handle) => Some(handle),
-                                Err(e) => {
-                                    println!("Error creating image from mpc output: {}", e);
-                                    self.backup_album_image.clone()
-                                }
-                            }
-                        },
-                        Err(e) => {
-                            println!("Error running mpc readpicture: {}", e);
-                            self.backup_album_image.clone()
-                        },
-                };
+                let what = format!("album art of {}", song.file);
+                self.image_album = self.album_art.as_ref()
+                    .and_then(|art| upload_image(ctx, art, &what))
+                    .or_else(|| self.backup_album_image.clone());
             },
             None => {
-                self.image_background = None;
+                self.album_art = None;
             },
         }
     }
@@ -530,92 +1195,292 @@ impl MyWindowHandler {
 fn main() {
     simple_logger::SimpleLogger::new().init().unwrap();
 
-    let mpd_host = match std::env::var("MPD_HOST") {
-        Ok(val) => val,
-        Err(_) => "localhost".to_owned(),
-    };
-    let mpd_port = match std::env::var("MPD_PORT") {
-        Ok(val) => val,
-        Err(_) => "6600".to_owned(),
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        },
     };
-    let mpd_pass: String;
-    let mut mpd_host_split = mpd_host.split("@");
-    let mut mpd_host = mpd_host_split.next().unwrap().to_owned();
-    (mpd_host, mpd_pass) = match mpd_host_split.next() {
-        Some(host) => {
-            (host.to_owned(), mpd_host)
+    if args.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+
+    if let Some(artist) = args.which_background {
+        let config = Config::load(&paths::config_path());
+        if let Err(e) = which_background(&ArtistMatcher::new(&config.artists), &config, artist) {
+            exit_with(e);
+        }
+        return;
+    }
+
+    if args.check {
+        let errors = check();
+        match errors.first() {
+            Some(first) => {
+                println!("\n{} problem(s) found", errors.len());
+                std::process::exit(first.exit_code());
+            },
+            None => {
+                println!("\nEverything looks fine");
+                return;
+            },
+        }
+    }
+
+    if let Some(path) = &args.screenshot {
+        if let Err(e) = load_config().and_then(|config| take_screenshot(config, path, args.size.as_deref(), args.fixture.as_deref())) {
+            exit_with(e);
+        }
+        return;
+    }
+
+    if let Err(e) = run() {
+        exit_with(e);
+    }
+}
+
+fn run() -> Result<(), StartupError> {
+    let config = load_config()?;
+
+    let server = config.start_server().map(|server| server.name.clone());
+    let partition = config.partition_for(config.start_server()).map(str::to_owned);
+    let mut player = connect_mpd(&config, config.start_server())?;
+    let mut now_playing = NowPlaying::default();
+    now_playing.refresh(&mut player).map_err(|e| StartupError::from_mpd(player.address(), e))?;
+    info!("Status: {:?}", now_playing.status);
+
+    info!("Creating Window");
+    error::check_display()?;
+    let window = Window::new_fullscreen_borderless("MPD Display")
+        .map_err(|e| StartupError::NoDisplay(e.to_string()))?;
+
+    let mut handler = MyWindowHandler::new(config, Box::new(player), now_playing);
+    handler.server = server;
+    handler.partition = partition;
+    handler.watcher = FileWatcher::new(&paths::config_path(), &paths::asset_dirs(), &paths::artist_dirs());
+    handler.select_configured_theme();
+    window.run_loop(egui_speedy2d::WindowWrapper::new(handler));
+}
+
+// Unlike later reloads, a broken config file at startup is an error instead of falling back to
+// the defaults, so a typo doesn't go unnoticed
+fn load_config() -> Result<Config, StartupError> {
+    let path = paths::config_path();
+    Config::try_load(&path).map_err(|message| StartupError::Config { path, message })
+}
+
+fn exit_with(error: StartupError) -> ! {
+    eprintln!("{}", error);
+    std::process::exit(error.exit_code());
+}
+
+// Checks the config, assets and MPD connection without opening a window, printing each result.
+// Returns every problem found.
+fn check() -> Vec<StartupError> {
+    let mut errors = Vec::new();
+
+    let config_path = paths::config_path();
+    let config = match Config::try_load(&config_path) {
+        Ok(config) => {
+            match config_path.exists() {
+                true => println!("Config: {} ok", config_path.display()),
+                false => println!("Config: {} not found, using defaults", config_path.display()),
+            }
+            config
         },
-        None => {
-            (mpd_host, "".to_owned())
+        Err(message) => {
+            errors.push(StartupError::Config { path: config_path, message });
+            Config::default()
+        },
+    };
+    match theme::load_themes().iter().any(|theme| theme.name == config.theme) {
+        true => println!("Theme: {} ok", config.theme),
+        false => println!("Theme: {} not found, the first built-in theme would be used", config.theme),
+    }
+
+    let fonts = [paths::FONT_LIGHT, paths::FONT_BOLD].map(|relative| (relative, assets::check_font(relative)));
+    let images = [paths::LOGO, paths::ART_BACKUP].map(|relative| (relative, assets::check_image(relative)));
+    for (relative, result) in fonts.into_iter().chain(images) {
+        match result {
+            Ok(Some(path)) => println!("Asset {}: {} ok", relative, path.display()),
+            Ok(None) => println!("Asset {}: built-in", relative),
+            Err((path, message)) => errors.push(StartupError::Asset { path, message }),
         }
+    }
+
+    // Every profile, the display can be switched to any of them
+    let servers: Vec<Option<&ServerConfig>> = match config.servers.is_empty() {
+        true => vec![None],
+        false => config.servers.iter().map(Some).collect(),
     };
-    //info!("Host: {}, Port: {}, Pass: {}", mpd_host, mpd_port, mpd_pass);
+    for server in servers {
+        let name = server.map(|server| format!(" ({})", server.name)).unwrap_or_default();
+        let partition = config.partition_for(server).map(|partition| format!(", partition {}", partition)).unwrap_or_default();
+        match connect_mpd(&config, server) {
+            Ok(mut player) => {
+                let mut now_playing = NowPlaying::default();
+                match now_playing.refresh(&mut player) {
+                    Ok(_) => println!("MPD{}: {}{} ok, {:?} with {} song(s) in the queue",
+                        name, player.address(), partition, now_playing.status.state, now_playing.status.queue_len),
+                    Err(e) => errors.push(StartupError::from_mpd(player.address(), e)),
+                }
+            },
+            Err(e) => errors.push(e),
+        }
+    }
 
+    match error::check_display() {
+        Ok(()) => println!("Display: ok"),
+        // Not a problem, the config can be checked over ssh
+        Err(e) => println!("Display: {}", e),
+    }
 
-    info!("Starting MPD connection and initializing client");
-    let mut mpd_client = Client::connect(format!("{}:{}", mpd_host, mpd_port)).unwrap();
-    if mpd_pass != "" {
-        let _ = mpd_client.login(&mpd_pass);
-    }
-    let mpd_status = mpd_client.status().unwrap();
-    let (current_song, current_song_id) = match mpd_status.song {
-        Some(queue_place) => {
-            (mpd_client.playlistid(queue_place.id).unwrap(), queue_place.id.0)
+    for error in &errors {
+        println!("\n{}", error);
+    }
+    errors
+}
+
+// Renders a single frame to a PNG file instead of opening a window
+fn take_screenshot(mut config: Config, path: &Path, size: Option<&str>, fixture: Option<&Path>) -> Result<(), StartupError> {
+    let size = screenshot::parse_size(size.unwrap_or("1920x1080")).map_err(StartupError::Screenshot)?;
+    let mut player: Box<dyn PlayerBackend> = match fixture {
+        Some(fixture_path) => {
+            let fixture = Fixture::load(fixture_path)
+                .and_then(|fixture| fixture.backend(fixture_path.parent().unwrap_or(Path::new("."))))
+                .map_err(StartupError::Screenshot)?;
+            Box::new(fixture)
         },
-        None => (None, u32::MAX),
+        None => {
+            let mut player = connect_mpd(&config, config.start_server())?;
+            // Caught here, where it can still be told apart from a fixture problem
+            player.status().map_err(|e| StartupError::from_mpd(player.address(), e))?;
+            Box::new(player)
+        },
+    };
+    let mut now_playing = NowPlaying::default();
+    now_playing.refresh(player.as_mut()).map_err(|e| StartupError::Screenshot(format!("error reading the player state: {}", e)))?;
+    // A single frame has no audio to show
+    config.visualizer.enabled = false;
+    let mut handler = MyWindowHandler::new(config, player, now_playing);
+    if fixture.is_none() {
+        handler.server = handler.config.start_server().map(|server| server.name.clone());
+        handler.partition = handler.config.partition_for(handler.config.start_server()).map(str::to_owned);
+    }
+    handler.width = size.x;
+    handler.height = size.y;
+    handler.select_configured_theme();
+    let image = screenshot::render(size, |graphics| {
+        handler.init_images(graphics);
+        handler.update_text();
+        graphics.clear_screen(handler.color_background);
+        handler.draw_display(graphics);
+    }).map_err(StartupError::Screenshot)?;
+    image.save(path).map_err(|e| StartupError::Screenshot(format!("error saving {}: {}", path.display(), e)))?;
+    info!("Saved screenshot to {}", path.display());
+    Ok(())
+}
+
+// The server profile, or MPD_HOST and MPD_PORT without one, switched to the configured partition
+fn connect_mpd(config: &Config, server: Option<&ServerConfig>) -> Result<MpdBackend, StartupError> {
+    let host = match server {
+        Some(server) => MpdHost::from_server(server)?,
+        None => MpdHost::from_env()?,
     };
-    let next_song = match mpd_status.nextsong {
-        Some(queue_place) => {
-            mpd_client.playlistid(queue_place.id).unwrap()
+    info!("Starting MPD connection and initializing client");
+    let mut player = MpdBackend::connect(&host.address, host.password.as_deref()).map_err(|e| StartupError::from_mpd(&host.address, e))?;
+    if let Some(partition) = config.partition_for(server) {
+        player.switch_partition(partition).map_err(|e| StartupError::from_mpd(&host.address, e))?;
+    }
+    Ok(player)
+}
+
+// Prints every background name tried for an artist and where the image was found
+fn which_background(matcher: &ArtistMatcher, config: &Config, artist: Option<String>) -> Result<(), StartupError> {
+    let candidates = match artist {
+        Some(artist) => {
+            println!("Artist: {}", artist);
+            matcher.candidates(&artist)
+        },
+        None => {
+            let mut player = connect_mpd(config, config.start_server())?;
+            match player.current_song() {
+                Ok(Some(song)) => {
+                    println!("File: {}", song.file);
+                    println!("Artist: {}", song.artist.as_deref().unwrap_or(""));
+                    for (key, value) in song.tags.iter().filter(|(key, _)| key == "AlbumArtist" || key == "MUSICBRAINZ_ARTISTID") {
+                        println!("{}: {}", key, value);
+                    }
+                    matcher.song_candidates(&song)
+                },
+                Ok(None) => {
+                    println!("Nothing playing");
+                    return Ok(());
+                },
+                Err(e) => return Err(StartupError::from_mpd(player.address(), e)),
+            }
         },
-        None => None,
     };
-    let queue_len = mpd_status.queue_len;
-    println!("Status: {:?}", mpd_status);
+    let images = ArtistImages::list();
+    for name in candidates {
+        match images.find(&name) {
+            Some(source) => {
+                println!("  {:?} -> {}", name, source);
+                return Ok(());
+            },
+            None => println!("  {:?} -> not found", name),
+        }
+    }
+    println!("No background image found");
+    Ok(())
+}
+
+// Lays out `text`, shrinking it until it's narrower than `max_width`. Gives up at a tiny size, so a
+// theme with no room for the text can't hang the display.
+fn fit_text(font: &Font, text: &str, mut font_size: f32, max_width: f32) -> FormattedTextBlock {
+    let mut layout = font.layout_text(text, font_size, TextOptions::new());
+    while layout.width() >= max_width && font_size > 4.0 {
+        font_size *= 0.95;
+        layout = font.layout_text(text, font_size, TextOptions::new());
+    }
+    layout
+}
 
+// Hands decoded pixels to the GPU, None (after logging what it was) if that fails
+fn upload_image(ctx: &mut Graphics2D, image: &RgbaImage, what: &str) -> Option<ImageHandle> {
+    match ctx.create_image_from_raw_pixels(ImageDataType::RGBA, ImageSmoothingMode::Linear, image.dimensions(), image.as_raw()) {
+        Ok(handle) => Some(handle),
+        Err(e) => {
+            warn!("Error creating image from {}: {}", what, e);
+            None
+        },
+    }
+}
 
-    info!("Creating Window");
-    let window = Window::new_fullscreen_borderless("MPD Display").unwrap();
-
-
-    window.run_loop(egui_speedy2d::WindowWrapper::new(MyWindowHandler{
-        width: 0,
-        height: 0,
-        fullscreen: true,
-        bar_hover: false,
-        cursor_visible: true,
-        show_debug_window: false,
-        startup: true,
-
-        mpd_client,
-        mpd_status,
-        current_song,
-        current_song_id,
-        queue_len,
-        next_song,
-
-        font_light: Font::new(include_bytes!("../assets/font/CircularStd-Book.otf")).unwrap(),
-        font_bold: Font::new(include_bytes!("../assets/font/CircularStd-Bold.otf")).unwrap(),
-
-        text_playingfromqueue: None,
-        text_queue: None,
-        text_title: None,
-        text_artist: None,
-        text_upnext: None,
-        text_next_song: None,
-
-        text_color_background: Color::from_int_rgb(156,156,156),
-        text_color_foreground: Color::from_int_rgb(255,255,255),
-        text_color_midground: Color::from_int_rgb(195,195,195),
-        color_background_image_tint: Color::from_int_rgba(75, 75, 75, 255),
-        color_background: Color::from_int_rgb(50,50,50),
-        color_accent: Color::from_int_rgb(29, 185, 84),
-
-        image_background: None,
-        image_watermark: None,
-        image_album: None,
-        backup_album_image: None,
-    }));
+// Draws the image cut into a circle, rotated clockwise by `angle` radians
+fn draw_image_disc(graphics: &mut Graphics2D, rect: &Rectangle<f32>, angle: f32, image: &ImageHandle) {
+    const SEGMENTS: usize = 96;
+    let center = *rect.top_left() + rect.size() / 2.0;
+    let radius = rect.width().min(rect.height()) / 2.0;
+    let image_center = Vec2::new(0.5, 0.5);
+    let point = |segment: usize| {
+        let segment_angle = segment as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+        let screen = center + Vec2::new((segment_angle + angle).cos(), (segment_angle + angle).sin()) * radius;
+        let image_coords = image_center + Vec2::new(segment_angle.cos(), segment_angle.sin()) * 0.5;
+        (screen, image_coords)
+    };
+    for segment in 0..SEGMENTS {
+        let (screen_start, image_start) = point(segment);
+        let (screen_end, image_end) = point(segment + 1);
+        graphics.draw_triangle_image_tinted_three_color(
+            [center, screen_start, screen_end],
+            [Color::WHITE; 3],
+            [image_center, image_start, image_end],
+            image,
+        );
+    }
 }
 
 fn get_scaled_image_rect(image: &ImageHandle, scale: f32, top_left: (f32, f32)) -> Rectangle<f32> {
@@ -625,3 +1490,102 @@ fn get_scaled_image_rect(image: &ImageHandle, scale: f32, top_left: (f32, f32))
     let bottom_right = (top_left.0 + new_width, top_left.1 + new_height);
     Rectangle::from_tuples(top_left, bottom_right)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mpd_display_rs::fake_player::FakeBackend;
+
+    const VIEWPORT: Vec2 = Vec2::new(800.0, 480.0);
+
+    // A handler on the default config, where gestures are off
+    fn handler(player: FakeBackend) -> MyWindowHandler {
+        let mut handler = MyWindowHandler::new(Config::default(), Box::new(player), NowPlaying::default());
+        handler.width = VIEWPORT.x as u32;
+        handler.height = VIEWPORT.y as u32;
+        handler
+    }
+
+    fn click(handler: &mut MyWindowHandler, position: Vec2) -> Option<SettingsItem> {
+        handler.mouse_position = position;
+        handler.press()
+    }
+
+    fn row_center(count: usize, row: usize) -> Vec2 {
+        let rect = &overlay::row_rects(VIEWPORT, count)[row];
+        *rect.top_left() + rect.size() / 2.0
+    }
+
+    #[test]
+    fn clicking_a_queue_row_plays_it() {
+        let mut player = FakeBackend::default();
+        player.push("a.flac", Some("A"), None);
+        player.push("b.flac", Some("B"), None);
+        let mut handler = handler(player);
+        handler.open_queue().unwrap();
+        click(&mut handler, row_center(2, 1));
+        assert!(handler.overlay.is_none());
+        let current = handler.player.current_song().unwrap().unwrap();
+        assert_eq!(current.title.as_deref(), Some("B"));
+    }
+
+    #[test]
+    fn clicking_beside_the_rows_closes_the_overlay() {
+        let mut handler = handler(FakeBackend::default());
+        handler.overlay = Some(Overlay::Settings);
+        assert_eq!(click(&mut handler, Vec2::new(1.0, 1.0)), None);
+        assert!(handler.overlay.is_none());
+    }
+
+    #[test]
+    fn clicking_a_settings_row_picks_it() {
+        let mut handler = handler(FakeBackend::default());
+        handler.overlay = Some(Overlay::Settings);
+        let picked = click(&mut handler, row_center(SETTINGS_ITEMS.len(), 0));
+        assert_eq!(picked, Some(SETTINGS_ITEMS[0]));
+        assert!(handler.overlay.is_some());
+    }
+
+    #[test]
+    fn clicking_a_server_switches_to_it() {
+        let mut handler = handler(FakeBackend::default());
+        handler.config.servers = ["kitchen", "living room"].iter()
+            .map(|name| ServerConfig {
+                name: name.to_string(),
+                host: "localhost".to_owned(),
+                port: 6600,
+                password_file: None,
+                password: None,
+                partition: None,
+            })
+            .collect();
+        handler.open_servers();
+        click(&mut handler, row_center(2, 1));
+        assert!(handler.overlay.is_none());
+        assert!(matches!(handler.switch_to, Some(Switch::Server(ref name)) if name == "living room"));
+    }
+
+    #[test]
+    fn clicking_a_partition_switches_to_it() {
+        let mut player = FakeBackend::default();
+        player.partitions.push("bedroom".to_owned());
+        let mut handler = handler(player);
+        handler.open_partitions();
+        click(&mut handler, row_center(2, 1));
+        assert!(handler.overlay.is_none());
+        assert!(matches!(handler.switch_to, Some(Switch::Partition(ref name)) if name == "bedroom"));
+    }
+
+    #[test]
+    fn clicking_an_output_toggles_it() {
+        let mut handler = handler(FakeBackend::default());
+        assert!(!handler.config.gestures.enabled);
+        handler.open_outputs();
+        click(&mut handler, row_center(1, 0));
+        match &handler.overlay {
+            Some(Overlay::Outputs(outputs)) => assert!(!outputs[0].enabled),
+            _ => panic!("the outputs overlay closed"),
+        }
+        assert!(!handler.player.outputs().unwrap()[0].enabled);
+    }
+}
@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use log::{info, warn};
+use crate::config::ServerConfig;
+use crate::error::StartupError;
+
+// Where MPD is and the password to log in with
+#[derive(Debug, PartialEq)]
+pub struct MpdHost {
+    pub address: String,
+    pub password: Option<String>,
+}
+
+impl MpdHost {
+    pub fn from_env() -> Result<MpdHost, StartupError> {
+        MpdHost::from_vars(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+    }
+
+    pub fn from_server(server: &ServerConfig) -> Result<MpdHost, StartupError> {
+        let password = match (&server.password_file, &server.password) {
+            (Some(path), _) => Some(read_password_file(path)?),
+            (None, password) => password.clone(),
+        };
+        Ok(MpdHost {
+            address: format!("{}:{}", server.host, server.port),
+            password,
+        })
+    }
+
+    // MPD_HOST and MPD_PORT like mpc, MPD_HOST may be formatted as password@host. The password can
+    // also come from MPD_PASSWORD, or the file MPD_PASSWORD_FILE points to, which keeps it out of
+    // MPD_HOST where other tools print it.
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<MpdHost, StartupError> {
+        let host = var("MPD_HOST").unwrap_or_else(|| "localhost".to_owned());
+        let port = var("MPD_PORT").unwrap_or_else(|| "6600".to_owned());
+        let (host, host_password) = match host.rsplit_once('@') {
+            Some((password, host)) => (host.to_owned(), Some(password.to_owned())),
+            None => (host, None),
+        };
+        let password = match (var("MPD_PASSWORD_FILE"), var("MPD_PASSWORD")) {
+            (Some(path), _) => {
+                info!("Reading the MPD password from {}", path);
+                Some(read_password_file(Path::new(&path))?)
+            },
+            (None, Some(password)) => Some(password),
+            (None, None) => host_password.filter(|password| !password.is_empty()),
+        };
+        Ok(MpdHost {
+            address: format!("{}:{}", host, port),
+            password,
+        })
+    }
+}
+
+fn read_password_file(path: &Path) -> Result<String, StartupError> {
+    let error = |message: String| StartupError::PasswordFile { path: PathBuf::from(path), message };
+    let contents = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+    warn_if_readable_by_others(path);
+    // Editors tend to leave a newline at the end
+    let password = contents.trim_end_matches(['\n', '\r']);
+    if password.is_empty() {
+        return Err(error("the file is empty".to_owned()));
+    }
+    Ok(password.to_owned())
+}
+
+#[cfg(unix)]
+fn warn_if_readable_by_others(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    if let Ok(metadata) = fs::metadata(path) {
+        if metadata.permissions().mode() & 0o044 != 0 {
+            warn!("The MPD password file {} can be read by other users, chmod 600 it", path.display());
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn warn_if_readable_by_others(_path: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn host(vars: &[(&str, &str)]) -> Result<MpdHost, StartupError> {
+        let vars: HashMap<String, String> = vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        MpdHost::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(host(&[]).unwrap(), MpdHost { address: "localhost:6600".to_owned(), password: None });
+        let custom = host(&[("MPD_HOST", "music.lan"), ("MPD_PORT", "6601")]).unwrap();
+        assert_eq!(custom, MpdHost { address: "music.lan:6601".to_owned(), password: None });
+    }
+
+    #[test]
+    fn password_in_host() {
+        let host = host(&[("MPD_HOST", "p@ss@music.lan")]).unwrap();
+        assert_eq!(host, MpdHost { address: "music.lan:6600".to_owned(), password: Some("p@ss".to_owned()) });
+    }
+
+    #[test]
+    fn password_variable_wins_over_host() {
+        let host = host(&[("MPD_HOST", "old@music.lan"), ("MPD_PASSWORD", "new")]).unwrap();
+        assert_eq!(host.address, "music.lan:6600");
+        assert_eq!(host.password.as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn password_file() {
+        let dir = TestDir::new("host");
+        let path = dir.write("password", "from file\n");
+        let path = path.to_str().unwrap();
+        let host = host(&[("MPD_PASSWORD_FILE", path), ("MPD_PASSWORD", "from variable")]).unwrap();
+        assert_eq!(host.password.as_deref(), Some("from file"));
+    }
+
+    #[test]
+    fn server_profile() {
+        let dir = TestDir::new("host");
+        let path = dir.write("password", "patio password");
+        let server: ServerConfig = toml::from_str(&format!("name = \"Patio\"\nhost = \"patio.lan\"\npassword_file = {:?}", path)).unwrap();
+        let host = MpdHost::from_server(&server).unwrap();
+        assert_eq!(host, MpdHost { address: "patio.lan:6600".to_owned(), password: Some("patio password".to_owned()) });
+    }
+
+    #[test]
+    fn broken_password_files() {
+        let dir = TestDir::new("host");
+        let empty = dir.write("empty", "\n");
+        assert!(matches!(host(&[("MPD_PASSWORD_FILE", empty.to_str().unwrap())]), Err(StartupError::PasswordFile { .. })));
+        let missing = dir.path().join("missing").join("password");
+        assert!(matches!(host(&[("MPD_PASSWORD_FILE", missing.to_str().unwrap())]), Err(StartupError::PasswordFile { .. })));
+    }
+}
@@ -0,0 +1,168 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use image::RgbaImage;
+use image::imageops::FilterType;
+use mpd::song::Song;
+use notify_rust::{Hint, Image, Notification, Timeout};
+use log::{info, warn};
+use crate::config::NotificationConfig;
+
+struct PendingNotification {
+    changed_at: Instant,
+    summary: String,
+    body: String,
+    art: Option<Arc<RgbaImage>>,
+}
+
+// Sends a freedesktop notification (org.freedesktop.Notifications) when the song changes
+pub struct Notifier {
+    config: NotificationConfig,
+    pending: Option<PendingNotification>,
+    // ID of the last notification we sent, so the next one replaces it instead of stacking up
+    last_id: Arc<AtomicU32>,
+}
+
+impl Notifier {
+    pub fn new(config: NotificationConfig) -> Notifier {
+        Notifier {
+            config,
+            pending: None,
+            last_id: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    // Keeps whatever is pending and the last notification's ID, so the next song still replaces
+    // it after a config reload
+    pub fn set_config(&mut self, config: NotificationConfig) {
+        if !config.enabled {
+            self.pending = None;
+        }
+        self.config = config;
+    }
+
+    // Queues a notification for the new song. It is only sent once the song has stayed current
+    // for `debounce_ms`, a newer song change replaces whatever is still pending.
+    pub fn song_changed(&mut self, song: Option<&Song>, art: Option<Arc<RgbaImage>>) {
+        if !self.config.enabled {
+            return;
+        }
+        self.pending = song.map(|song| {
+            let title = song.title.clone().unwrap_or_else(|| song.file.clone());
+            let artist = song.artist.clone().unwrap_or_default();
+            let album = song.tags.iter()
+                .find(|(key, _)| key == "Album")
+                .map(|(_, value)| value.clone())
+                .unwrap_or_default();
+            let body = match (artist.is_empty(), album.is_empty()) {
+                (false, false) => format!("{}\n{}", artist, album),
+                (false, true) => artist,
+                (true, false) => album,
+                (true, true) => "".to_owned(),
+            };
+            PendingNotification {
+                changed_at: Instant::now(),
+                summary: title,
+                body,
+                art,
+            }
+        });
+    }
+
+    // Called every frame, sends the pending notification once it has settled
+    pub fn poll(&mut self, fullscreen: bool) {
+        let pending = match self.pending.take() {
+            Some(pending) if pending.changed_at.elapsed() >= Duration::from_millis(self.config.debounce_ms) => pending,
+            not_settled => {
+                self.pending = not_settled;
+                return;
+            },
+        };
+        if fullscreen && self.config.windowed_only {
+            return;
+        }
+        let icon_size = self.config.icon_size;
+        let timeout = self.config.timeout_ms;
+        let last_id = self.last_id.clone();
+        // D-Bus calls block, keep them off the draw loop
+        thread::spawn(move || {
+            let mut notification = Notification::new();
+            notification
+                .appname("MPD Display")
+                .summary(&pending.summary)
+                .body(&pending.body)
+                .hint(Hint::Category("x-gnome.music".to_owned()))
+                .timeout(Timeout::Milliseconds(timeout));
+            if let Some(art) = pending.art {
+                let icon = icon(&art, icon_size);
+                match Image::from_rgba(icon.width() as i32, icon.height() as i32, icon.into_raw()) {
+                    Ok(image) => { notification.image_data(image); },
+                    Err(e) => warn!("Error converting album art for notification: {}", e),
+                }
+            }
+            let previous_id = last_id.load(Ordering::Relaxed);
+            if previous_id != 0 {
+                notification.id(previous_id);
+            }
+            match notification.show() {
+                Ok(handle) => {
+                    last_id.store(handle.id(), Ordering::Relaxed);
+                    info!("Sent notification for {}", pending.summary);
+                },
+                Err(e) => warn!("Error sending notification: {}", e),
+            }
+        });
+    }
+}
+
+// Scales the art down to fit in a `size` square, keeping its aspect ratio
+fn icon(art: &RgbaImage, size: u32) -> RgbaImage {
+    let (width, height) = art.dimensions();
+    let longest = width.max(height);
+    if longest <= size {
+        return art.clone();
+    }
+    let scale = |side: u32| ((side as u64 * size as u64 / longest as u64) as u32).max(1);
+    image::imageops::resize(art, scale(width), scale(height), FilterType::Triangle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled() -> NotificationConfig {
+        NotificationConfig { enabled: true, ..NotificationConfig::default() }
+    }
+
+    fn song() -> Song {
+        Song { file: "song.flac".to_owned(), title: Some("Title".to_owned()), ..Song::default() }
+    }
+
+    #[test]
+    fn reloading_keeps_the_pending_notification_and_id() {
+        let mut notifier = Notifier::new(enabled());
+        notifier.last_id.store(7, Ordering::Relaxed);
+        notifier.song_changed(Some(&song()), None);
+        notifier.set_config(NotificationConfig { debounce_ms: 0, ..enabled() });
+        assert_eq!(notifier.pending.as_ref().map(|pending| pending.summary.as_str()), Some("Title"));
+        assert_eq!(notifier.last_id.load(Ordering::Relaxed), 7);
+    }
+
+    #[test]
+    fn disabling_drops_the_pending_notification() {
+        let mut notifier = Notifier::new(enabled());
+        notifier.song_changed(Some(&song()), None);
+        notifier.set_config(NotificationConfig::default());
+        assert!(notifier.pending.is_none());
+    }
+
+    #[test]
+    fn icon_keeps_the_aspect_ratio() {
+        assert_eq!(icon(&RgbaImage::new(600, 300), 128).dimensions(), (128, 64));
+        assert_eq!(icon(&RgbaImage::new(300, 900), 128).dimensions(), (42, 128));
+        assert_eq!(icon(&RgbaImage::new(1000, 1), 128).dimensions(), (128, 1));
+        // Small enough already
+        assert_eq!(icon(&RgbaImage::new(100, 80), 128).dimensions(), (100, 80));
+    }
+}
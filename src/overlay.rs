@@ -0,0 +1,100 @@
+use speedy2d::Graphics2D;
+use speedy2d::color::Color;
+use speedy2d::dimen::Vec2;
+use speedy2d::font::{Font, TextLayout, TextOptions};
+use speedy2d::shape::Rectangle;
+use mpd::Output;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingsItem {
+    Theme,
+    Server,
+    Partition,
+    Outputs,
+    Fullscreen,
+    Cursor,
+    Clock,
+    Random,
+    Repeat,
+    Close,
+}
+
+pub const SETTINGS_ITEMS: [SettingsItem; 10] = [
+    SettingsItem::Theme,
+    SettingsItem::Server,
+    SettingsItem::Partition,
+    SettingsItem::Outputs,
+    SettingsItem::Fullscreen,
+    SettingsItem::Cursor,
+    SettingsItem::Clock,
+    SettingsItem::Random,
+    SettingsItem::Repeat,
+    SettingsItem::Close,
+];
+
+pub struct QueueEntry {
+    // Position in the queue, to play it when tapped
+    pub position: u32,
+    pub label: String,
+    pub current: bool,
+}
+
+// Full screen lists on top of the display, opened with gestures
+pub enum Overlay {
+    // The rest of the queue from the current song onwards
+    Queue(Vec<QueueEntry>),
+    Settings,
+    // Server profiles from the config, to switch to another MPD
+    Servers,
+    // Partitions of the current server, each with its own queue and player
+    Partitions(Vec<String>),
+    // Audio outputs, tapping one turns it on or off
+    Outputs(Vec<Output>),
+}
+
+// Top and height of the first row, as fractions of the viewport height
+const ROWS_TOP: f32 = 0.15;
+const ROW_HEIGHT: f32 = 0.075;
+
+// Rectangles of up to `count` rows, fewer if they don't all fit. Used for both drawing and
+// hit-testing.
+pub fn row_rects(viewport: Vec2, count: usize) -> Vec<Rectangle> {
+    let row_height = viewport.y * ROW_HEIGHT;
+    let fits = ((viewport.y * (0.95 - ROWS_TOP)) / row_height).floor() as usize;
+    let width = viewport.x * 0.7;
+    let left = (viewport.x - width) / 2.0;
+    (0..count.min(fits))
+        .map(|i| {
+            let top = viewport.y * ROWS_TOP + row_height * i as f32;
+            Rectangle::from_tuples((left, top), (left + width, top + row_height))
+        })
+        .collect()
+}
+
+pub fn row_at(rows: &[Rectangle], position: Vec2) -> Option<usize> {
+    rows.iter().position(|row| row.contains(position))
+}
+
+// `labels` are drawn in `rows`, the highlighted ones in the accent color. `fonts` are bold and
+// light, `colors` foreground and accent.
+pub fn draw(graphics: &mut Graphics2D, viewport: Vec2, title: &str, rows: &[Rectangle], labels: &[(String, bool)], fonts: (&Font, &Font), colors: (Color, Color)) {
+    let (font_bold, font_light) = fonts;
+    let (foreground, accent) = colors;
+    graphics.draw_rectangle(Rectangle::new(Vec2::ZERO, viewport), Color::from_rgba(0.0, 0.0, 0.0, 0.8));
+    let text_title = font_bold.layout_text(title, viewport.y * 0.05, TextOptions::new());
+    graphics.draw_text(((viewport.x - text_title.width()) / 2.0, viewport.y * 0.05), foreground, &text_title);
+    for (row, (label, highlighted)) in rows.iter().zip(labels) {
+        let color = if *highlighted { accent } else { foreground };
+        let text = font_light.layout_text(label, row.height() * 0.45, TextOptions::new());
+        let padding = row.height() * 0.25;
+        graphics.draw_text_cropped(
+            (row.left() + padding, row.top() + (row.height() - text.height()) / 2.0),
+            Rectangle::from_tuples((row.left(), row.top()), (row.right() - padding, row.bottom())),
+            color,
+            &text,
+        );
+        // Thin separator under every row
+        let line = Rectangle::from_tuples((row.left(), row.bottom() - 1.0), (row.right(), row.bottom()));
+        graphics.draw_rectangle(line, Color::from_rgba(foreground.r(), foreground.g(), foreground.b(), 0.2));
+    }
+}
@@ -0,0 +1,82 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+pub const APP_NAME: &str = "mpd-display-rs";
+
+// Where replacements for the built-in assets go, relative to a data directory
+pub const LOGO: &str = "assets/logo.png";
+pub const ART_BACKUP: &str = "assets/art_backup";
+pub const FONT_LIGHT: &str = "assets/font/CircularStd-Book.otf";
+pub const FONT_BOLD: &str = "assets/font/CircularStd-Bold.otf";
+
+// Used when no data directory has a copy of the asset
+pub const EMBEDDED_LOGO: &[u8] = include_bytes!("../assets/logo.png");
+pub const EMBEDDED_ART_BACKUP: &[u8] = include_bytes!("../assets/art_backup");
+pub const EMBEDDED_FONT_LIGHT: &[u8] = include_bytes!("../assets/font/CircularStd-Book.otf");
+pub const EMBEDDED_FONT_BOLD: &[u8] = include_bytes!("../assets/font/CircularStd-Bold.otf");
+
+// Directories assets and artist images are looked up in, in order of preference:
+// $XDG_DATA_HOME/mpd-display-rs (~/.local/share/mpd-display-rs), each of $XDG_DATA_DIRS
+// (/usr/local/share and /usr/share), then the working directory so running from a clone of the
+// repo keeps working.
+pub fn data_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(data_home) = xdg_dir("XDG_DATA_HOME", ".local/share") {
+        dirs.push(data_home.join(APP_NAME));
+    }
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_owned());
+    for dir in env::split_paths(&data_dirs).filter(|dir| dir.is_absolute()) {
+        dirs.push(dir.join(APP_NAME));
+    }
+    dirs.push(PathBuf::from("."));
+    dirs
+}
+
+// First data directory containing `relative`
+pub fn find_data_file(relative: &str) -> Option<PathBuf> {
+    data_dirs().into_iter()
+        .map(|dir| dir.join(relative))
+        .find(|path| path.exists())
+}
+
+// Every existing `assets/` directory, most preferred first
+pub fn asset_dirs() -> Vec<PathBuf> {
+    existing_subdirs("assets")
+}
+
+// Every existing `artists/` directory, most preferred first
+pub fn artist_dirs() -> Vec<PathBuf> {
+    existing_subdirs("artists")
+}
+
+// $XDG_CONFIG_HOME/mpd-display-rs/config.toml (~/.config/mpd-display-rs/config.toml), falling back
+// to ./config.toml if only that one exists
+pub fn config_path() -> PathBuf {
+    let local = PathBuf::from("./config.toml");
+    match xdg_dir("XDG_CONFIG_HOME", ".config") {
+        Some(config_home) => {
+            let path = config_home.join(APP_NAME).join("config.toml");
+            if !path.exists() && local.exists() { local } else { path }
+        },
+        None => local,
+    }
+}
+
+fn existing_subdirs(name: &str) -> Vec<PathBuf> {
+    data_dirs().into_iter()
+        .map(|dir| dir.join(name))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+// An XDG base directory variable, or its default relative to $HOME. Relative paths are invalid
+// according to the spec and ignored.
+fn xdg_dir(var: &str, home_default: &str) -> Option<PathBuf> {
+    match env::var_os(var).map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => Some(dir),
+        _ => env::var_os("HOME").map(|home| Path::new(&home).join(home_default)),
+    }
+}
@@ -0,0 +1,636 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use mpd::{Client, Idle, Output, Subsystem};
+use mpd::error::{Error, ProtoError, Result};
+use mpd::song::{Id, Song};
+use mpd::status::Status;
+use log::{info, warn};
+
+// Everything the display needs from the music player, so the logic on top of it can be tested
+// against a fake instead of a running MPD
+pub trait PlayerBackend {
+    fn status(&mut self) -> Result<Status>;
+    // Song in the queue with this id, None if it's gone
+    fn song(&mut self, id: Id) -> Result<Option<Song>>;
+    fn current_song(&mut self) -> Result<Option<Song>>;
+    fn queue(&mut self) -> Result<Vec<Song>>;
+    fn toggle_pause(&mut self) -> Result<()>;
+    fn next(&mut self) -> Result<()>;
+    fn prev(&mut self) -> Result<()>;
+    // Plays the song at this position in the queue
+    fn play_position(&mut self, position: u32) -> Result<()>;
+    fn set_volume(&mut self, volume: i8) -> Result<()>;
+    fn set_random(&mut self, random: bool) -> Result<()>;
+    fn set_repeat(&mut self, repeat: bool) -> Result<()>;
+    // Picture embedded in a file, None if it has none
+    fn read_picture(&mut self, file: &str) -> Result<Option<Vec<u8>>>;
+    // Names of the partitions, each with its own queue and player (MPD 0.22 and later)
+    fn partitions(&mut self) -> Result<Vec<String>>;
+    // Switches this client to another partition, which sticks after reconnecting
+    fn switch_partition(&mut self, name: &str) -> Result<()>;
+    // Audio outputs of the current partition
+    fn outputs(&mut self) -> Result<Vec<Output>>;
+    fn set_output(&mut self, id: u32, enabled: bool) -> Result<()>;
+    // Reports changes to these subsystems as MPD announces them, until the watcher is dropped
+    fn watch(&mut self, subsystems: &[Subsystem]) -> Result<ChangeWatcher>;
+}
+
+// Changes announced by MPD, waited for on a separate connection so the display doesn't have to
+// ask every frame
+pub struct ChangeWatcher {
+    changes: Receiver<Subsystem>,
+    // Set when the watcher is dropped, so the thread stops instead of reconnecting
+    stop: Arc<AtomicBool>,
+    // The connection the thread is waiting on, shut down when the watcher is dropped
+    stream: Arc<Mutex<Option<TcpStream>>>,
+}
+
+impl ChangeWatcher {
+    // A watcher fed by something else than MPD, e.g. a fake player
+    pub fn new(changes: Receiver<Subsystem>) -> ChangeWatcher {
+        ChangeWatcher {
+            changes,
+            stop: Arc::new(AtomicBool::new(false)),
+            stream: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    // Changes announced since the last poll
+    pub fn poll(&self) -> Vec<Subsystem> {
+        self.changes.try_iter().collect()
+    }
+}
+
+impl Drop for ChangeWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(stream) = self.stream.lock().ok().and_then(|mut stream| stream.take()) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+// After a failed reconnect, wait this long before trying again
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+// Commands run on the draw thread, so a host that's gone can't be allowed to hang it for the
+// minutes the system would wait. MPD on the network answers in milliseconds.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Connects again when the connection is lost, e.g. when MPD restarts
+pub struct MpdBackend {
+    address: String,
+    password: Option<String>,
+    // Switched to right after connecting, the default partition if None
+    partition: Option<String>,
+    connection: Option<Connection>,
+    // When reconnecting last failed
+    failed_at: Option<Instant>,
+}
+
+struct Connection {
+    client: Client,
+    // Second handle on the client's socket, for commands the mpd crate doesn't have. MPD only
+    // ever answers commands, so once the client has read a whole response nothing is left
+    // buffered and the two can take turns.
+    raw: TcpStream,
+}
+
+impl MpdBackend {
+    pub fn connect(address: &str, password: Option<&str>) -> Result<MpdBackend> {
+        let mut backend = MpdBackend::new(address, password);
+        backend.connection = Some(Connection::open(address, password, None)?);
+        Ok(backend)
+    }
+
+    // Doesn't connect until the first command, so switching to a server that is down doesn't
+    // fail right away
+    pub fn new(address: &str, password: Option<&str>) -> MpdBackend {
+        MpdBackend {
+            address: address.to_owned(),
+            password: password.map(str::to_owned),
+            partition: None,
+            connection: None,
+            failed_at: None,
+        }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    // Runs a command, reconnecting first if the connection was lost. Anything but an error from
+    // MPD itself (an ACK) leaves the connection in an unknown state, so it gets dropped.
+    fn run<T>(&mut self, command: impl FnOnce(&mut Connection) -> Result<T>) -> Result<T> {
+        if self.connection.is_none() {
+            if self.failed_at.is_some_and(|at| at.elapsed() < RECONNECT_INTERVAL) {
+                return Err(Error::Io(io::Error::new(ErrorKind::NotConnected, "not connected to MPD")));
+            }
+            info!("Reconnecting to MPD at {}", self.address);
+            match Connection::open(&self.address, self.password.as_deref(), self.partition.as_deref()) {
+                Ok(connection) => {
+                    self.connection = Some(connection);
+                    self.failed_at = None;
+                },
+                Err(e) => {
+                    self.failed_at = Some(Instant::now());
+                    return Err(e);
+                },
+            }
+        }
+        let connection = self.connection.as_mut().expect("connected above");
+        let result = command(connection).map_err(timed_out);
+        match &result {
+            Ok(_) | Err(Error::Server(_)) => {},
+            Err(_) => self.connection = None,
+        }
+        result
+    }
+}
+
+impl Connection {
+    fn open(address: &str, password: Option<&str>, partition: Option<&str>) -> Result<Connection> {
+        let stream = connect(address)?;
+        stream.set_read_timeout(Some(COMMAND_TIMEOUT))?;
+        stream.set_write_timeout(Some(COMMAND_TIMEOUT))?;
+        let raw = stream.try_clone()?;
+        let mut client = Client::new(stream).map_err(timed_out)?;
+        if let Some(password) = password {
+            client.login(password)?;
+        }
+        let mut connection = Connection {
+            client,
+            raw,
+        };
+        if let Some(partition) = partition {
+            connection.command(&format!("partition {}", quote(partition)))?;
+        }
+        Ok(connection)
+    }
+
+    // Runs a command the mpd crate doesn't have, returns the pairs it answered with
+    fn command(&mut self, command: &str) -> Result<Vec<(String, String)>> {
+        writeln!(self.raw, "{}", command)?;
+        let mut reader = BufReader::new(&self.raw);
+        let mut pairs = Vec::new();
+        loop {
+            let line = read_line(&mut reader)?;
+            if line == "OK" {
+                return Ok(pairs);
+            }
+            if line.starts_with("ACK ") {
+                return Err(Error::Server(line.parse()?));
+            }
+            match line.split_once(": ") {
+                Some((key, value)) => pairs.push((key.to_owned(), value.to_owned())),
+                None => return Err(Error::Proto(ProtoError::NotPair)),
+            }
+        }
+    }
+
+    // readpicture sends the picture in chunks, each one asked for with the offset it starts at
+    fn read_picture(&mut self, file: &str) -> Result<Option<Vec<u8>>> {
+        let mut picture = Vec::new();
+        loop {
+            writeln!(self.raw, "readpicture {} {}", quote(file), picture.len())?;
+            let mut reader = BufReader::new(&self.raw);
+            let mut size: Option<usize> = None;
+            let binary: usize = loop {
+                let line = read_line(&mut reader)?;
+                if line == "OK" {
+                    // No picture
+                    return Ok(None);
+                }
+                if line.starts_with("ACK ") {
+                    return Err(Error::Server(line.parse()?));
+                }
+                match line.split_once(": ") {
+                    Some(("size", value)) => size = Some(value.parse()?),
+                    Some(("binary", value)) => break value.parse()?,
+                    _ => {},
+                }
+            };
+            let mut chunk = vec![0; binary];
+            reader.read_exact(&mut chunk)?;
+            picture.extend_from_slice(&chunk);
+            // The chunk is followed by a newline, then OK
+            if !read_line(&mut reader)?.is_empty() || read_line(&mut reader)? != "OK" {
+                return Err(Error::Proto(ProtoError::NotOk));
+            }
+            let size = size.ok_or(Error::Proto(ProtoError::NoField("size")))?;
+            if binary == 0 || picture.len() >= size {
+                return Ok(Some(picture));
+            }
+        }
+    }
+}
+
+impl PlayerBackend for MpdBackend {
+    fn status(&mut self) -> Result<Status> {
+        self.run(|connection| connection.client.status())
+    }
+
+    fn song(&mut self, id: Id) -> Result<Option<Song>> {
+        self.run(|connection| connection.client.playlistid(id))
+    }
+
+    fn current_song(&mut self) -> Result<Option<Song>> {
+        self.run(|connection| connection.client.currentsong())
+    }
+
+    fn queue(&mut self) -> Result<Vec<Song>> {
+        self.run(|connection| connection.client.queue())
+    }
+
+    fn toggle_pause(&mut self) -> Result<()> {
+        self.run(|connection| connection.client.toggle_pause())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.run(|connection| connection.client.next())
+    }
+
+    fn prev(&mut self) -> Result<()> {
+        self.run(|connection| connection.client.prev())
+    }
+
+    fn play_position(&mut self, position: u32) -> Result<()> {
+        self.run(|connection| connection.client.switch(position))
+    }
+
+    fn set_volume(&mut self, volume: i8) -> Result<()> {
+        self.run(|connection| connection.client.volume(volume))
+    }
+
+    fn set_random(&mut self, random: bool) -> Result<()> {
+        self.run(|connection| connection.client.random(random))
+    }
+
+    fn set_repeat(&mut self, repeat: bool) -> Result<()> {
+        self.run(|connection| connection.client.repeat(repeat))
+    }
+
+    fn read_picture(&mut self, file: &str) -> Result<Option<Vec<u8>>> {
+        self.run(|connection| connection.read_picture(file))
+    }
+
+    fn partitions(&mut self) -> Result<Vec<String>> {
+        let pairs = self.run(|connection| connection.command("listpartitions"))?;
+        Ok(pairs.into_iter().filter(|(key, _)| key == "partition").map(|(_, name)| name).collect())
+    }
+
+    fn outputs(&mut self) -> Result<Vec<Output>> {
+        self.run(|connection| connection.client.outputs())
+    }
+
+    fn set_output(&mut self, id: u32, enabled: bool) -> Result<()> {
+        self.run(|connection| connection.client.output(id, enabled))
+    }
+
+    // A connection of its own, since idling blocks it until something changes. It's opened on the
+    // watcher's thread, so an unreachable server doesn't hold up the caller.
+    fn watch(&mut self, subsystems: &[Subsystem]) -> Result<ChangeWatcher> {
+        let (sender, changes) = mpsc::channel();
+        let watcher = ChangeWatcher::new(changes);
+        let target = ChangeTarget {
+            address: self.address.clone(),
+            password: self.password.clone(),
+            partition: self.partition.clone(),
+            subsystems: subsystems.to_vec(),
+        };
+        let stop = watcher.stop.clone();
+        let stream = watcher.stream.clone();
+        thread::spawn(move || watch_changes(target, sender, stop, stream));
+        Ok(watcher)
+    }
+
+    // Without a connection the partition is only remembered, and switched to when connecting
+    fn switch_partition(&mut self, name: &str) -> Result<()> {
+        if self.connection.is_some() {
+            self.run(|connection| connection.command(&format!("partition {}", quote(name))))?;
+        }
+        self.partition = Some(name.to_owned());
+        Ok(())
+    }
+}
+
+// Where a ChangeWatcher connects to and what it waits for
+struct ChangeTarget {
+    address: String,
+    password: Option<String>,
+    partition: Option<String>,
+    subsystems: Vec<Subsystem>,
+}
+
+// Waits for changes until the watcher is dropped, reconnecting like MpdBackend::run when the
+// connection is lost: right away the first time, then every RECONNECT_INTERVAL
+fn watch_changes(target: ChangeTarget, sender: Sender<Subsystem>, stop: Arc<AtomicBool>, current: Arc<Mutex<Option<TcpStream>>>) {
+    let mut reconnecting = false;
+    let mut failed = false;
+    while !stop.load(Ordering::Relaxed) {
+        if failed {
+            let retry_at = Instant::now() + RECONNECT_INTERVAL;
+            while !stop.load(Ordering::Relaxed) && Instant::now() < retry_at {
+                thread::sleep(Duration::from_millis(50));
+            }
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+        }
+        let opened = Connection::open(&target.address, target.password.as_deref(), target.partition.as_deref())
+            .and_then(|connection| {
+                // Idling waits for as long as nothing changes
+                connection.raw.set_read_timeout(None)?;
+                let stream = connection.raw.try_clone()?;
+                Ok((connection, stream))
+            });
+        let mut connection = match opened {
+            Ok((connection, stream)) => {
+                if let Ok(mut current) = current.lock() {
+                    *current = Some(stream);
+                }
+                connection
+            },
+            Err(e) => {
+                if !failed {
+                    warn!("Error connecting to MPD at {} to watch for changes, retrying: {}", target.address, e);
+                }
+                failed = true;
+                reconnecting = true;
+                continue;
+            },
+        };
+        failed = false;
+        // Dropped while connecting, after which nothing would shut this connection down
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+        // Whatever changed before this connection went unnoticed, unless it's the first try
+        if reconnecting && target.subsystems.iter().any(|subsystem| sender.send(*subsystem).is_err()) {
+            return;
+        }
+        loop {
+            match connection.client.wait(&target.subsystems) {
+                Ok(changed) if !changed.is_empty() => {
+                    if changed.into_iter().any(|subsystem| sender.send(subsystem).is_err()) {
+                        return;
+                    }
+                },
+                // The mpd crate reads a closed connection as an empty list of changes
+                Ok(_) | Err(_) => break,
+            }
+        }
+        if let Ok(mut current) = current.lock() {
+            *current = None;
+        }
+        if !stop.load(Ordering::Relaxed) {
+            info!("Lost the connection watching MPD at {} for changes, reconnecting", target.address);
+        }
+        reconnecting = true;
+    }
+}
+
+// Tries every address the host name resolves to, giving each one CONNECT_TIMEOUT
+fn connect(address: &str) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(ErrorKind::NotFound, format!("{} didn't resolve to any address", address));
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+// A read that hit COMMAND_TIMEOUT reports WouldBlock, which reads like MPD was just busy
+fn timed_out(error: Error) -> Error {
+    match error {
+        Error::Io(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+            Error::Io(io::Error::new(ErrorKind::TimedOut, "MPD didn't answer in time"))
+        },
+        e => e,
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(Error::Io(ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(line.trim_end_matches('\n').to_owned())
+}
+
+// Quotes a command argument, escaping backslashes and double quotes
+fn quote(argument: &str) -> String {
+    format!("\"{}\"", argument.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// What's playing, refreshed from the backend every frame
+pub struct NowPlaying {
+    pub status: Status,
+    pub current_song: Option<Song>,
+    // u32::MAX while nothing is playing
+    pub current_song_id: u32,
+    pub next_song: Option<Song>,
+}
+
+impl Default for NowPlaying {
+    fn default() -> Self {
+        NowPlaying {
+            status: Status::default(),
+            current_song: None,
+            current_song_id: u32::MAX,
+            next_song: None,
+        }
+    }
+}
+
+impl NowPlaying {
+    // Fetches the status and the current and next song, returns whether the current song changed
+    pub fn refresh(&mut self, backend: &mut dyn PlayerBackend) -> Result<bool> {
+        self.status = backend.status()?;
+        let (current_song, current_song_id) = match self.status.song {
+            Some(place) => match backend.song(place.id)? {
+                Some(song) => (Some(song), place.id.0),
+                // Removed between the two commands, the next refresh will see the new status
+                None => return Ok(false),
+            },
+            None => (None, u32::MAX),
+        };
+        self.next_song = match self.status.nextsong {
+            Some(place) => backend.song(place.id)?,
+            None => None,
+        };
+        let changed = current_song_id != self.current_song_id;
+        self.current_song = current_song;
+        self.current_song_id = current_song_id;
+        Ok(changed)
+    }
+
+    // Title (or file name if the song has no title) and artist
+    pub fn title_and_artist(&self) -> (String, String) {
+        match &self.current_song {
+            Some(song) => song_title_and_artist(song),
+            None => ("Nothing playing".to_owned(), "".to_owned()),
+        }
+    }
+
+    pub fn up_next_text(&self) -> String {
+        match &self.next_song {
+            Some(song) => match song_title_and_artist(song) {
+                (title, artist) if artist.is_empty() => title,
+                (title, artist) => format!("{} - {}", artist, title),
+            },
+            None => "Nothing".to_owned(),
+        }
+    }
+
+    pub fn queue_text(&self) -> String {
+        format!("{} tracks left", self.status.queue_len)
+    }
+}
+
+fn song_title_and_artist(song: &Song) -> (String, String) {
+    (
+        song.title.clone().unwrap_or_else(|| song.file.clone()),
+        song.artist.clone().unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use mpd::status::State;
+    use crate::fake_player::FakeBackend;
+    use super::*;
+
+    fn backend_with_queue() -> FakeBackend {
+        let mut backend = FakeBackend::default();
+        backend.push("a.flac", Some("First"), Some("Artist A"));
+        backend.push("b.flac", Some("Second"), None);
+        backend.push("c.flac", None, Some("Artist C"));
+        backend
+    }
+
+    #[test]
+    fn nothing_playing() {
+        let mut backend = backend_with_queue();
+        let mut now_playing = NowPlaying::default();
+        assert!(!now_playing.refresh(&mut backend).unwrap());
+        assert_eq!(now_playing.title_and_artist(), ("Nothing playing".to_owned(), "".to_owned()));
+        assert_eq!(now_playing.up_next_text(), "Nothing");
+        assert_eq!(now_playing.queue_text(), "3 tracks left");
+    }
+
+    #[test]
+    fn song_changes_are_reported_once() {
+        let mut backend = backend_with_queue();
+        let mut now_playing = NowPlaying::default();
+        backend.play(0);
+        assert!(now_playing.refresh(&mut backend).unwrap());
+        assert!(!now_playing.refresh(&mut backend).unwrap());
+        assert_eq!(now_playing.title_and_artist(), ("First".to_owned(), "Artist A".to_owned()));
+
+        backend.next().unwrap();
+        assert!(now_playing.refresh(&mut backend).unwrap());
+        assert_eq!(now_playing.title_and_artist(), ("Second".to_owned(), "".to_owned()));
+
+        // Pausing isn't a song change
+        backend.toggle_pause().unwrap();
+        assert!(!now_playing.refresh(&mut backend).unwrap());
+        assert_eq!(now_playing.status.state, State::Pause);
+    }
+
+    #[test]
+    fn up_next_text() {
+        let mut backend = backend_with_queue();
+        let mut now_playing = NowPlaying::default();
+        backend.play(0);
+        now_playing.refresh(&mut backend).unwrap();
+        // No artist
+        assert_eq!(now_playing.up_next_text(), "Second");
+        backend.play(1);
+        now_playing.refresh(&mut backend).unwrap();
+        // No title, falls back to the file
+        assert_eq!(now_playing.up_next_text(), "Artist C - c.flac");
+        backend.play(2);
+        now_playing.refresh(&mut backend).unwrap();
+        assert_eq!(now_playing.up_next_text(), "Nothing");
+        backend.set_repeat(true).unwrap();
+        now_playing.refresh(&mut backend).unwrap();
+        assert_eq!(now_playing.up_next_text(), "Artist A - First");
+    }
+
+    #[test]
+    fn queue_edits() {
+        let mut backend = backend_with_queue();
+        let mut now_playing = NowPlaying::default();
+        backend.play(1);
+        now_playing.refresh(&mut backend).unwrap();
+        backend.push("d.flac", Some("Fourth"), Some("Artist D"));
+        assert!(!now_playing.refresh(&mut backend).unwrap());
+        assert_eq!(now_playing.queue_text(), "4 tracks left");
+
+        // Removing the next song changes up next but not the current song
+        backend.remove(2);
+        assert!(!now_playing.refresh(&mut backend).unwrap());
+        assert_eq!(now_playing.up_next_text(), "Artist D - Fourth");
+
+        // Removing the current song stops playback
+        backend.remove(1);
+        assert!(now_playing.refresh(&mut backend).unwrap());
+        assert_eq!(now_playing.title_and_artist().0, "Nothing playing");
+    }
+
+    #[test]
+    fn disconnects_keep_the_last_state() {
+        let mut backend = backend_with_queue();
+        let mut now_playing = NowPlaying::default();
+        backend.play(0);
+        now_playing.refresh(&mut backend).unwrap();
+        backend.disconnect();
+        assert!(now_playing.refresh(&mut backend).is_err());
+        assert!(backend.toggle_pause().is_err());
+        assert_eq!(now_playing.title_and_artist().0, "First");
+
+        backend.reconnect();
+        backend.play(2);
+        assert!(now_playing.refresh(&mut backend).unwrap());
+        assert_eq!(backend.commands, Vec::<String>::new());
+    }
+
+    #[test]
+    fn commands_are_recorded() {
+        let mut backend = backend_with_queue();
+        backend.play_position(1).unwrap();
+        backend.set_volume(40).unwrap();
+        backend.prev().unwrap();
+        assert_eq!(backend.commands, ["play 1", "setvol 40", "previous"]);
+        assert_eq!(backend.current, Some(0));
+        assert_eq!(backend.volume, 40);
+    }
+
+    #[test]
+    fn watchers_hear_about_output_changes() {
+        let mut backend = FakeBackend::default();
+        let watcher = backend.watch(&[Subsystem::Output]).unwrap();
+        backend.set_output(0, false).unwrap();
+        assert_eq!(watcher.poll(), [Subsystem::Output]);
+        assert!(watcher.poll().is_empty());
+        assert!(!backend.outputs().unwrap()[0].enabled);
+        assert!(backend.set_output(5, true).is_err());
+        assert_eq!(backend.commands, ["disableoutput 0"]);
+        // Dropped watchers are forgotten
+        drop(watcher);
+        backend.set_output(0, true).unwrap();
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote(r#"dir/a "b" \c.flac"#), r#""dir/a \"b\" \\c.flac""#);
+    }
+}
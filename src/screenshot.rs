@@ -0,0 +1,205 @@
+use std::ffi::c_void;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use serde::Deserialize;
+use speedy2d::{GLRenderer, Graphics2D};
+use speedy2d::dimen::UVec2;
+use speedy2d::image::ImageDataType;
+use glutin::{ContextBuilder, NotCurrent};
+use glutin::dpi::PhysicalSize;
+use glutin::event_loop::EventLoop;
+use image::RgbaImage;
+use mpd::status::State;
+use log::info;
+use mpd_display_rs::fake_player::FakeBackend;
+
+// A made up player state to take screenshots of, instead of whatever MPD is playing
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fixture {
+    // "play", "pause" or "stop"
+    #[serde(default = "playing")]
+    pub state: String,
+    // Seconds into the current song
+    #[serde(default)]
+    pub elapsed: f32,
+    #[serde(default = "volume")]
+    pub volume: i8,
+    // Position of the current song in `song`
+    #[serde(default)]
+    pub current: usize,
+    #[serde(default)]
+    pub song: Vec<FixtureSong>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureSong {
+    pub file: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    // Seconds
+    #[serde(default = "duration")]
+    pub duration: f32,
+    // Album art, relative to the fixture file
+    pub art: Option<String>,
+}
+
+fn playing() -> String { "play".to_owned() }
+fn volume() -> i8 { 50 }
+fn duration() -> f32 { 200.0 }
+
+impl Fixture {
+    pub fn load(path: &Path) -> Result<Fixture, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("error reading fixture {}: {}", path.display(), e))?;
+        toml::from_str(&source).map_err(|e| format!("error parsing fixture {}: {}", path.display(), e))
+    }
+
+    // A fake player in the fixture's state. `dir` is where art paths are relative to.
+    pub fn backend(&self, dir: &Path) -> Result<FakeBackend, String> {
+        let mut backend = FakeBackend::default();
+        for song in &self.song {
+            backend.push(&song.file, song.title.as_deref(), song.artist.as_deref());
+            if let Some(last) = backend.queue.last_mut() {
+                last.duration = Some(Duration::from_secs_f32(song.duration));
+            }
+            if let Some(art) = &song.art {
+                let art_path = dir.join(art);
+                let picture = fs::read(&art_path).map_err(|e| format!("error reading art {}: {}", art_path.display(), e))?;
+                backend.pictures.insert(song.file.clone(), picture);
+            }
+        }
+        backend.state = match self.state.as_str() {
+            "play" => State::Play,
+            "pause" => State::Pause,
+            "stop" => State::Stop,
+            other => return Err(format!("invalid fixture state {:?}, expected play, pause or stop", other)),
+        };
+        if backend.state != State::Stop && !self.song.is_empty() {
+            if self.current >= self.song.len() {
+                return Err(format!("fixture current song {} is past the end of the queue", self.current));
+            }
+            backend.current = Some(self.current);
+        }
+        backend.elapsed = Duration::from_secs_f32(self.elapsed);
+        backend.volume = self.volume;
+        Ok(backend)
+    }
+}
+
+// Parses a size like "1920x1080"
+pub fn parse_size(size: &str) -> Result<UVec2, String> {
+    let invalid = || format!("invalid size {:?}, expected something like 1920x1080", size);
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let width: u32 = width.parse().map_err(|_| invalid())?;
+    let height: u32 = height.parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok(UVec2::new(width, height))
+}
+
+// Renders one frame without a window and returns its pixels. Uses an offscreen EGL context where
+// there is one, which Mesa backs with llvmpipe on machines without a GPU, so it works on CI
+// machines without a display. Otherwise it needs a display to create a headless context on.
+pub fn render(size: UVec2, draw: impl FnOnce(&mut Graphics2D)) -> Result<RgbaImage, String> {
+    #[cfg(egl)]
+    let egl_error = match crate::egl::HeadlessContext::new(size) {
+        Ok(context) => {
+            info!("Rendering offscreen with EGL");
+            return draw_frame(size, |name| context.get_proc_address(name), draw);
+        },
+        Err(e) => {
+            info!("Offscreen EGL unavailable ({}), rendering on the display instead", e);
+            e
+        },
+    };
+    #[cfg(not(egl))]
+    let egl_error = "not supported on this platform".to_owned();
+
+    let context = display_context(PhysicalSize::new(size.x, size.y)).map_err(|e| format!("no offscreen EGL ({}) and {}", egl_error, e))?;
+    let context = unsafe { context.make_current() }.map_err(|(_, e)| format!("error activating OpenGL context: {}", e))?;
+    draw_frame(size, |name| context.get_proc_address(name), draw)
+}
+
+// Draws with the OpenGL context that's current on this thread
+fn draw_frame(size: UVec2, get_proc_address: impl FnMut(&str) -> *const c_void, draw: impl FnOnce(&mut Graphics2D)) -> Result<RgbaImage, String> {
+    let mut renderer = unsafe { GLRenderer::new_for_gl_context(size, get_proc_address) }
+        .map_err(|e| format!("error creating renderer: {}", e))?;
+    let pixels = renderer.draw_frame(|graphics| {
+        draw(graphics);
+        graphics.capture(ImageDataType::RGBA)
+    });
+    let size = pixels.size();
+    RgbaImage::from_raw(size.x, size.y, pixels.into_data()).ok_or_else(|| "captured frame has the wrong size".to_owned())
+}
+
+fn display_context(size: PhysicalSize<u32>) -> Result<glutin::Context<NotCurrent>, String> {
+    // Creating the event loop panics without a display, so check first
+    if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+        return Err("no display to render on (install Mesa's libEGL or run under Xvfb)".to_owned());
+    }
+    let event_loop = EventLoop::new();
+    ContextBuilder::new().build_headless(&event_loop, size).map_err(|e| format!("error creating headless OpenGL context: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use speedy2d::color::Color;
+    use speedy2d::shape::Rectangle;
+    use mpd_display_rs::player::{NowPlaying, PlayerBackend};
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("1920x1080"), Ok(UVec2::new(1920, 1080)));
+        assert!(parse_size("1920").is_err());
+        assert!(parse_size("0x1080").is_err());
+        assert!(parse_size("widexhigh").is_err());
+    }
+
+    #[test]
+    fn demo_fixture() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        let fixture = Fixture::load(&dir.join("demo.toml")).unwrap();
+        let mut backend = fixture.backend(&dir).unwrap();
+        let mut now_playing = NowPlaying::default();
+        assert!(now_playing.refresh(&mut backend).unwrap());
+        assert_eq!(now_playing.title_and_artist(), ("First Song".to_owned(), "Demo Artist".to_owned()));
+        assert_eq!(now_playing.up_next_text(), "Demo Artist - Second Song");
+        assert_eq!(now_playing.status.elapsed, Some(Duration::from_secs(187)));
+        assert_eq!(now_playing.status.duration, Some(Duration::from_secs(204)));
+        assert!(backend.read_picture("Demo Artist/Demo Album/01 First Song.flac").unwrap().is_some());
+    }
+
+    #[test]
+    fn invalid_fixtures() {
+        let backend = |source: &str| toml::from_str::<Fixture>(source).map_err(|e| e.to_string())
+            .and_then(|fixture| fixture.backend(Path::new(".")));
+        assert!(backend("state = \"loud\"").is_err());
+        assert!(backend("current = 1\n[[song]]\nfile = \"a.flac\"").is_err());
+        assert!(backend("[[song]]\nfile = \"a.flac\"\nart = \"missing.png\"").is_err());
+        assert!(backend("colour = 1").is_err());
+        assert!(backend("state = \"stop\"").is_ok());
+    }
+
+    // Needs libEGL or a display, skipped without either
+    #[test]
+    fn renders_a_frame() {
+        let frame = render(UVec2::new(64, 48), |graphics| {
+            graphics.clear_screen(Color::BLUE);
+            graphics.draw_rectangle(Rectangle::from_tuples((0.0, 0.0), (32.0, 24.0)), Color::RED);
+        });
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) => {
+                eprintln!("Skipping, no OpenGL to render with: {}", e);
+                return;
+            },
+        };
+        assert_eq!(frame.dimensions(), (64, 48));
+        assert_eq!(frame.get_pixel(10, 10).0, [255, 0, 0, 255]);
+        assert_eq!(frame.get_pixel(50, 40).0, [0, 0, 255, 255]);
+    }
+}
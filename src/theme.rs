@@ -0,0 +1,336 @@
+use std::fs;
+use serde::Deserialize;
+use speedy2d::dimen::Vec2;
+use log::{info, warn};
+use crate::paths;
+
+// Themes compiled into the binary, the first one is the default
+const BUILTIN_THEMES: [&str; 3] = [
+    include_str!("themes/spotify.toml"),
+    include_str!("themes/vinyl.toml"),
+    include_str!("themes/minimal.toml"),
+];
+
+// A named layout, with an optional separate arrangement for portrait and square screens
+#[derive(Debug, Clone, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    #[serde(flatten)]
+    pub landscape: Arrangement,
+    pub portrait: Option<Arrangement>,
+    // The portrait arrangement is used when width / height is below this
+    #[serde(default = "portrait_below")]
+    pub portrait_below: f32,
+}
+
+impl Theme {
+    pub fn arrangement(&self, viewport: Vec2) -> &Arrangement {
+        match &self.portrait {
+            Some(portrait) if viewport.x / viewport.y < self.portrait_below => portrait,
+            _ => &self.landscape,
+        }
+    }
+}
+
+fn portrait_below() -> f32 { 1.2 }
+
+// Where every element of the display goes. All positions and sizes are lengths relative to the
+// viewport, so a theme works at any resolution.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Arrangement {
+    pub header: HeaderTheme,
+    pub album: AlbumTheme,
+    pub title: TitleTheme,
+    pub progress: ProgressTheme,
+    pub up_next: UpNextTheme,
+    // Optional so themes written before there was a volume slider keep loading
+    #[serde(default)]
+    pub volume: VolumeTheme,
+    #[serde(default)]
+    pub transport: TransportTheme,
+}
+
+// Watermark logo with "PLAYING FROM" and the queue length next to it
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeaderTheme {
+    #[serde(default = "visible")]
+    pub visible: bool,
+    #[serde(flatten)]
+    pub position: Position,
+    pub logo_height: Length,
+    pub playing_from_size: Length,
+    pub queue_size: Length,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlbumTheme {
+    #[serde(default = "visible")]
+    pub visible: bool,
+    #[serde(flatten)]
+    pub position: Position,
+    pub size: Length,
+    #[serde(default)]
+    pub shape: AlbumShape,
+    // Only used for discs, how fast the disc turns while playing
+    #[serde(default)]
+    pub spin_rpm: f32,
+}
+
+// Title with the artist underneath
+#[derive(Debug, Clone, Deserialize)]
+pub struct TitleTheme {
+    #[serde(flatten)]
+    pub position: Position,
+    #[serde(default)]
+    pub align: Align,
+    pub title_size: Length,
+    pub artist_size: Length,
+    pub gap: Length,
+    // Text is shrunk until it fits in this width
+    pub max_width: Length,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProgressTheme {
+    #[serde(flatten)]
+    pub position: Position,
+    pub width: Length,
+    pub height: Length,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpNextTheme {
+    #[serde(default = "visible")]
+    pub visible: bool,
+    #[serde(flatten)]
+    pub position: Position,
+    pub size: Length,
+}
+
+// Volume slider, only shown while hovered or in use
+#[derive(Debug, Clone, Deserialize)]
+pub struct VolumeTheme {
+    #[serde(default = "visible")]
+    pub visible: bool,
+    #[serde(flatten)]
+    pub position: Position,
+    pub width: Length,
+    pub height: Length,
+}
+
+impl Default for VolumeTheme {
+    // Bottom right, underneath the end of a full width progress bar
+    fn default() -> Self {
+        VolumeTheme {
+            visible: true,
+            position: Position { x: Length::default_from("0.925w"), y: Length::default_from("0.95h"), anchor: Anchor::TopRight },
+            width: Length::default_from("0.125w"),
+            height: Length::default_from("0.006h"),
+        }
+    }
+}
+
+// Previous, play/pause and next buttons, placed as one row
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransportTheme {
+    #[serde(default = "visible")]
+    pub visible: bool,
+    #[serde(flatten)]
+    pub position: Position,
+    // Width and height of each button
+    pub size: Length,
+    pub gap: Length,
+}
+
+impl Default for TransportTheme {
+    // Centered just above a progress bar at 90% of the height
+    fn default() -> Self {
+        TransportTheme {
+            visible: true,
+            position: Position { x: Length::default_from("0.5w"), y: Length::default_from("0.88h"), anchor: Anchor::BottomCenter },
+            size: Length::default_from("0.045h"),
+            gap: Length::default_from("0.03h"),
+        }
+    }
+}
+
+fn visible() -> bool { true }
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlbumShape {
+    #[default]
+    Square,
+    Disc,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+// An element is placed so that its `anchor` point ends up at (x, y)
+#[derive(Debug, Clone, Deserialize)]
+pub struct Position {
+    pub x: Length,
+    pub y: Length,
+    #[serde(default)]
+    pub anchor: Anchor,
+}
+
+impl Position {
+    // Top left corner of an element of `size` placed at this position
+    pub fn top_left(&self, viewport: Vec2, size: Vec2) -> Vec2 {
+        let (x_factor, y_factor) = self.anchor.factors();
+        Vec2::new(
+            self.x.resolve(viewport) - size.x * x_factor,
+            self.y.resolve(viewport) - size.y * y_factor,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    TopCenter,
+    TopRight,
+    CenterLeft,
+    Center,
+    CenterRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+impl Anchor {
+    pub fn is_right(&self) -> bool {
+        self.factors().0 == 1.0
+    }
+
+    pub fn is_bottom(&self) -> bool {
+        self.factors().1 == 1.0
+    }
+
+    // How far along the element (0.0 to 1.0) the anchor point is, horizontally and vertically
+    fn factors(&self) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::TopCenter => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::CenterLeft => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::CenterRight => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::BottomCenter => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Unit {
+    Width,
+    Height,
+    // Whichever of width and height is smaller
+    Min,
+}
+
+// A sum of fractions of the viewport, written like "0.0625w + 0.3667min" or "1h - 0.1min"
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Length(Vec<(f32, Unit)>);
+
+impl Length {
+    // For defaults written in the code, where an invalid length is a bug
+    pub fn default_from(value: &str) -> Length {
+        Length::try_from(value.to_owned()).expect("default length is valid")
+    }
+
+    pub fn resolve(&self, viewport: Vec2) -> f32 {
+        self.0.iter()
+            .map(|(fraction, unit)| fraction * match unit {
+                Unit::Width => viewport.x,
+                Unit::Height => viewport.y,
+                Unit::Min => viewport.x.min(viewport.y),
+            })
+            .sum()
+    }
+}
+
+impl TryFrom<String> for Length {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid length {:?}, expected something like \"0.5w + 0.1min\"", value);
+        let mut terms = Vec::new();
+        let mut sign = 1.0;
+        for token in value.replace('+', " + ").replace('-', " - ").split_whitespace() {
+            match token {
+                "+" => sign = 1.0,
+                "-" => sign = -1.0,
+                _ => {
+                    let split = token.find(|c: char| c.is_ascii_alphabetic()).ok_or_else(invalid)?;
+                    let (number, unit) = token.split_at(split);
+                    let fraction: f32 = number.parse().map_err(|_| invalid())?;
+                    let unit = match unit {
+                        "w" => Unit::Width,
+                        "h" => Unit::Height,
+                        "min" => Unit::Min,
+                        _ => return Err(invalid()),
+                    };
+                    terms.push((sign * fraction, unit));
+                    sign = 1.0;
+                },
+            }
+        }
+        if terms.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Length(terms))
+    }
+}
+
+// The built-in themes, followed by every `themes/*.toml` in the data directories. A theme with
+// the same name as an earlier one replaces it.
+pub fn load_themes() -> Vec<Theme> {
+    let mut themes = builtin_themes();
+    for dir in paths::data_dirs().iter().rev() {
+        let entries = match fs::read_dir(dir.join("themes")) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        let mut files: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        files.sort();
+        for file in files {
+            let theme: Theme = match fs::read_to_string(&file).map_err(|e| e.to_string())
+                .and_then(|source| toml::from_str(&source).map_err(|e| e.to_string())) {
+                Ok(theme) => theme,
+                Err(e) => {
+                    warn!("Error loading theme {}: {}", file.display(), e);
+                    continue;
+                },
+            };
+            info!("Loaded theme {} from {}", theme.name, file.display());
+            match themes.iter_mut().find(|existing| existing.name == theme.name) {
+                Some(existing) => *existing = theme,
+                None => themes.push(theme),
+            }
+        }
+    }
+    themes
+}
+
+pub fn builtin_themes() -> Vec<Theme> {
+    BUILTIN_THEMES.iter()
+        .map(|source| toml::from_str(source).expect("built-in theme is valid"))
+        .collect()
+}
@@ -0,0 +1,123 @@
+use speedy2d::Graphics2D;
+use speedy2d::color::Color;
+use speedy2d::dimen::Vec2;
+use speedy2d::shape::Rectangle;
+use crate::theme::TransportTheme;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransportButton {
+    Previous,
+    PlayPause,
+    Next,
+}
+
+const BUTTONS: [TransportButton; 3] = [TransportButton::Previous, TransportButton::PlayPause, TransportButton::Next];
+
+// Where each button goes, used for both drawing and hit-testing so they always agree
+pub fn button_rects(theme: &TransportTheme, viewport: Vec2) -> Vec<(TransportButton, Rectangle)> {
+    let size = theme.size.resolve(viewport);
+    let gap = theme.gap.resolve(viewport);
+    let row_size = Vec2::new(size * 3.0 + gap * 2.0, size);
+    let top_left = theme.position.top_left(viewport, row_size);
+    BUTTONS.iter()
+        .enumerate()
+        .map(|(i, button)| {
+            let button_top_left = top_left + Vec2::new((size + gap) * i as f32, 0.0);
+            (*button, Rectangle::new(button_top_left, button_top_left + Vec2::new(size, size)))
+        })
+        .collect()
+}
+
+fn hit(rects: &[(TransportButton, Rectangle)], position: Vec2) -> Option<TransportButton> {
+    rects.iter()
+        .find(|(_, rect)| rect.contains(position))
+        .map(|(button, _)| *button)
+}
+
+// Tracks which button is held down, a button only acts when released while still over it
+pub struct Transport {
+    pressed: Option<TransportButton>,
+}
+
+impl Transport {
+    pub fn new() -> Transport {
+        Transport {
+            pressed: None,
+        }
+    }
+
+    // Returns whether the press landed on a button
+    pub fn mouse_down(&mut self, rects: &[(TransportButton, Rectangle)], position: Vec2) -> bool {
+        self.pressed = hit(rects, position);
+        self.pressed.is_some()
+    }
+
+    // Returns the button that was clicked
+    pub fn mouse_up(&mut self, rects: &[(TransportButton, Rectangle)], position: Vec2) -> Option<TransportButton> {
+        let pressed = self.pressed.take()?;
+        match hit(rects, position) == Some(pressed) {
+            true => Some(pressed),
+            false => None,
+        }
+    }
+
+    // Play/pause is a filled circle with a cut out icon like Spotify's, previous and next are
+    // just the icon. Hovered buttons use the accent color and pressed ones shrink a little.
+    pub fn draw(&self, graphics: &mut Graphics2D, rects: &[(TransportButton, Rectangle)], mouse_position: Vec2, playing: bool, foreground: Color, accent: Color) {
+        let hovered = hit(rects, mouse_position);
+        for (button, rect) in rects {
+            let center = *rect.top_left() + rect.size() / 2.0;
+            let pressed = self.pressed == Some(*button) && hovered == Some(*button);
+            let scale = if pressed { 0.9 } else { 1.0 };
+            let radius = rect.width() / 2.0 * scale;
+            let color = if hovered == Some(*button) { accent } else { foreground };
+            match button {
+                TransportButton::PlayPause => {
+                    graphics.draw_circle(center, radius, color);
+                    match playing {
+                        true => draw_pause(graphics, center, radius * 0.45, Color::BLACK),
+                        false => draw_play(graphics, center, radius * 0.45, Color::BLACK),
+                    }
+                },
+                TransportButton::Previous => draw_skip(graphics, center, radius * 0.5, -1.0, color),
+                TransportButton::Next => draw_skip(graphics, center, radius * 0.5, 1.0, color),
+            }
+        }
+    }
+}
+
+fn draw_play(graphics: &mut Graphics2D, center: Vec2, half: f32, color: Color) {
+    // Nudged right so the triangle looks centered in the circle
+    let center = center + Vec2::new(half * 0.15, 0.0);
+    graphics.draw_triangle([
+        center + Vec2::new(-half * 0.8, -half),
+        center + Vec2::new(half, 0.0),
+        center + Vec2::new(-half * 0.8, half),
+    ], color);
+}
+
+fn draw_pause(graphics: &mut Graphics2D, center: Vec2, half: f32, color: Color) {
+    let bar = half * 0.6;
+    for x in [-half * 0.75, half * 0.75 - bar] {
+        graphics.draw_rectangle(Rectangle::new(center + Vec2::new(x, -half), center + Vec2::new(x + bar, half)), color);
+    }
+}
+
+// A triangle pointing in `direction` (-1.0 is left) against a bar
+fn draw_skip(graphics: &mut Graphics2D, center: Vec2, half: f32, direction: f32, color: Color) {
+    let tip = center + Vec2::new(half * 0.6 * direction, 0.0);
+    let base_x = center.x - half * 0.8 * direction;
+    let mut triangle = [
+        Vec2::new(base_x, center.y - half),
+        tip,
+        Vec2::new(base_x, center.y + half),
+    ];
+    // Vertices have to be clockwise
+    if direction < 0.0 {
+        triangle.reverse();
+    }
+    graphics.draw_triangle(triangle, color);
+    let bar = half * 0.3;
+    let bar_left = if direction > 0.0 { tip.x } else { tip.x - bar };
+    graphics.draw_rectangle(Rectangle::from_tuples((bar_left, center.y - half), (bar_left + bar, center.y + half)), color);
+}
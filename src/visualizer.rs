@@ -0,0 +1,350 @@
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use rustfft::FftPlanner;
+use rustfft::num_complex::Complex;
+use speedy2d::Graphics2D;
+use speedy2d::color::Color;
+use speedy2d::shape::Rectangle;
+use log::{info, warn};
+use crate::config::{VisualizerConfig, VisualizerStyle};
+
+// Samples per FFT, and how many new samples there are between two FFTs
+const FFT_SIZE: usize = 2048;
+const HOP_SIZE: usize = 1024;
+// Frequency range the spectrum bars are spread over (logarithmically)
+const MIN_FREQUENCY: f32 = 50.0;
+const MAX_FREQUENCY: f32 = 16000.0;
+// Quietest level that still shows up in the spectrum
+const FLOOR_DB: f32 = -60.0;
+// MPD stops writing to the FIFO while paused, levels older than this aren't drawn
+const STALE_AFTER: Duration = Duration::from_millis(300);
+// How long to wait before trying to open the FIFO again
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+// How often the reader checks for new audio, and whether it should stop, while there's none
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// Levels between 0.0 and 1.0, one per bar
+struct Levels {
+    values: Vec<f32>,
+    updated: Instant,
+}
+
+// Reads raw PCM from MPD's FIFO output on a background thread and turns it into bars
+pub struct Visualizer {
+    config: VisualizerConfig,
+    levels: Arc<Mutex<Levels>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Visualizer {
+    pub fn new(config: VisualizerConfig) -> Option<Visualizer> {
+        if !config.enabled || config.bars == 0 || config.channels == 0 {
+            return None;
+        }
+        let levels = Arc::new(Mutex::new(Levels {
+            values: vec![0.0; config.bars],
+            updated: Instant::now() - STALE_AFTER,
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_config = config.clone();
+        let thread_levels = levels.clone();
+        let thread_stop = stop.clone();
+        thread::spawn(move || read_fifo(thread_config, thread_levels, thread_stop));
+        Some(Visualizer {
+            config,
+            levels,
+            stop,
+        })
+    }
+
+    pub fn draw(&self, graphics: &mut Graphics2D, rect: &Rectangle, color: Color) {
+        let levels = match self.levels.lock() {
+            Ok(levels) => levels,
+            Err(_) => return,
+        };
+        if levels.updated.elapsed() > STALE_AFTER {
+            return;
+        }
+        let slot = rect.width() / levels.values.len() as f32;
+        let bar_width = slot * 0.7;
+        for (i, level) in levels.values.iter().enumerate() {
+            let height = level.clamp(0.0, 1.0) * rect.height();
+            if height < 1.0 {
+                continue;
+            }
+            let left = rect.left() + slot * i as f32 + (slot - bar_width) / 2.0;
+            let top = match self.config.style {
+                // Spectrum bars grow up from the bottom, waveform bars out from the middle
+                VisualizerStyle::Spectrum => rect.bottom() - height,
+                VisualizerStyle::Waveform => rect.top() + (rect.height() - height) / 2.0,
+            };
+            graphics.draw_rectangle(Rectangle::from_tuples((left, top), (left + bar_width, top + height)), color);
+        }
+    }
+}
+
+impl Drop for Visualizer {
+    fn drop(&mut self) {
+        // The FIFO is read without blocking, so the thread notices this within POLL_INTERVAL
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+// Keeps (re)opening the FIFO until the visualizer is dropped. The FIFO is opened without
+// blocking, so neither waiting for MPD to open its end nor waiting for audio while it's paused
+// keeps the thread from seeing `stop`.
+fn read_fifo(config: VisualizerConfig, levels: Arc<Mutex<Levels>>, stop: Arc<AtomicBool>) {
+    let mut analyzer = Analyzer::new(&config);
+    let mut warned = false;
+    while !stop.load(Ordering::Relaxed) {
+        let mut fifo = match open_fifo(&config.fifo_path) {
+            Ok(fifo) => fifo,
+            Err(e) => {
+                if !warned {
+                    warn!("Error opening visualizer FIFO {}, retrying in the background: {}", config.fifo_path.display(), e);
+                    warned = true;
+                }
+                sleep_unless_stopped(RETRY_INTERVAL, &stop);
+                continue;
+            },
+        };
+        info!("Reading audio from {}", config.fifo_path.display());
+        warned = false;
+        let frame_size = config.channels * 2;
+        let mut buffer = [0u8; 4096];
+        let mut pending: Vec<u8> = Vec::new();
+        while !stop.load(Ordering::Relaxed) {
+            let read = match fifo.read(&mut buffer) {
+                // Nothing has MPD's end open (0), or it has but isn't playing (WouldBlock)
+                Ok(0) => {
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                },
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => {
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                },
+                Err(e) => {
+                    warn!("Error reading visualizer FIFO {}: {}", config.fifo_path.display(), e);
+                    sleep_unless_stopped(RETRY_INTERVAL, &stop);
+                    break;
+                },
+            };
+            pending.extend_from_slice(&buffer[..read]);
+            let whole_frames = pending.len() / frame_size * frame_size;
+            for frame in pending[..whole_frames].chunks_exact(frame_size) {
+                // 16 bit little endian samples, mixed down to mono
+                let sum: f32 = frame.chunks_exact(2)
+                    .map(|sample| i16::from_le_bytes([sample[0], sample[1]]) as f32 / 32768.0)
+                    .sum();
+                if let Some(values) = analyzer.push(sum / config.channels as f32) {
+                    if let Ok(mut levels) = levels.lock() {
+                        levels.values.copy_from_slice(values);
+                        levels.updated = Instant::now();
+                    }
+                }
+            }
+            pending.drain(..whole_frames);
+        }
+    }
+}
+
+// Opening a FIFO normally blocks until something opens the other end
+#[cfg(unix)]
+fn open_fifo(path: &Path) -> io::Result<File> {
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::OpenOptionsExt;
+    OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(path)
+}
+
+#[cfg(not(unix))]
+fn open_fifo(path: &Path) -> io::Result<File> {
+    File::open(path)
+}
+
+fn sleep_unless_stopped(duration: Duration, stop: &AtomicBool) {
+    let until = Instant::now() + duration;
+    while !stop.load(Ordering::Relaxed) && Instant::now() < until {
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+struct Analyzer {
+    style: VisualizerStyle,
+    sample_rate: f32,
+    smoothing: f32,
+    fft: Arc<dyn rustfft::Fft<f32>>,
+    window: Vec<f32>,
+    // Ring buffer of the last FFT_SIZE samples, `position` is where the next one goes
+    samples: Vec<f32>,
+    position: usize,
+    new_samples: usize,
+    smoothed: Vec<f32>,
+}
+
+impl Analyzer {
+    fn new(config: &VisualizerConfig) -> Analyzer {
+        // Hann window, so the edges of each chunk don't smear the spectrum
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / FFT_SIZE as f32).cos())
+            .collect();
+        Analyzer {
+            style: config.style,
+            sample_rate: config.sample_rate as f32,
+            smoothing: config.smoothing.clamp(0.0, 0.99),
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            samples: vec![0.0; FFT_SIZE],
+            position: 0,
+            new_samples: 0,
+            smoothed: vec![0.0; config.bars],
+        }
+    }
+
+    // Adds one mono sample, returns new levels every HOP_SIZE samples
+    fn push(&mut self, sample: f32) -> Option<&[f32]> {
+        self.samples[self.position] = sample;
+        self.position = (self.position + 1) % FFT_SIZE;
+        self.new_samples += 1;
+        if self.new_samples < HOP_SIZE {
+            return None;
+        }
+        self.new_samples = 0;
+        let mut samples = self.samples[self.position..].to_vec();
+        samples.extend_from_slice(&self.samples[..self.position]);
+        let levels = match self.style {
+            VisualizerStyle::Spectrum => self.spectrum(&samples),
+            VisualizerStyle::Waveform => self.waveform(&samples),
+        };
+        for (smoothed, level) in self.smoothed.iter_mut().zip(levels) {
+            *smoothed = *smoothed * self.smoothing + level * (1.0 - self.smoothing);
+        }
+        Some(&self.smoothed)
+    }
+
+    fn spectrum(&self, samples: &[f32]) -> Vec<f32> {
+        let mut buffer: Vec<Complex<f32>> = samples.iter().zip(&self.window)
+            .map(|(sample, window)| Complex::new(sample * window, 0.0))
+            .collect();
+        self.fft.process(&mut buffer);
+        let bars = self.smoothed.len();
+        let max_frequency = MAX_FREQUENCY.min(self.sample_rate / 2.0);
+        let bin = |frequency: f32| ((frequency * FFT_SIZE as f32 / self.sample_rate) as usize).min(FFT_SIZE / 2);
+        (0..bars)
+            .map(|i| {
+                let frequency = |i: usize| MIN_FREQUENCY * (max_frequency / MIN_FREQUENCY).powf(i as f32 / bars as f32);
+                let low = bin(frequency(i));
+                let high = bin(frequency(i + 1)).max(low + 1).min(FFT_SIZE / 2);
+                let magnitude = buffer[low..high].iter().map(|c| c.norm()).fold(0.0, f32::max);
+                // A full scale sine ends up at about FFT_SIZE / 4 after the Hann window
+                let db = 20.0 * (magnitude / (FFT_SIZE as f32 / 4.0)).max(1e-9).log10();
+                ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+
+    // Peak level of each slice of the most recent samples
+    fn waveform(&self, samples: &[f32]) -> Vec<f32> {
+        let bars = self.smoothed.len();
+        let slice = (FFT_SIZE / bars).max(1);
+        (0..bars)
+            .map(|i| {
+                let start = (i * slice).min(FFT_SIZE);
+                let end = (start + slice).min(FFT_SIZE);
+                samples[start..end].iter().map(|sample| sample.abs()).fold(0.0, f32::max)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyzer(style: VisualizerStyle) -> Analyzer {
+        let config = VisualizerConfig {
+            style,
+            smoothing: 0.0,
+            ..VisualizerConfig::default()
+        };
+        Analyzer::new(&config)
+    }
+
+    // Feeds a full FFT_SIZE of samples, returns the levels after the last one
+    fn levels(analyzer: &mut Analyzer, sample: impl Fn(usize) -> f32) -> Vec<f32> {
+        let mut levels = None;
+        for i in 0..FFT_SIZE {
+            if let Some(values) = analyzer.push(sample(i)) {
+                levels = Some(values.to_vec());
+            }
+        }
+        levels.expect("levels after FFT_SIZE samples")
+    }
+
+    fn sine(frequency: f32) -> impl Fn(usize) -> f32 {
+        move |i| (std::f32::consts::TAU * frequency * i as f32 / 44100.0).sin()
+    }
+
+    // The bar a frequency falls in, spread like spectrum() spreads them
+    fn bar_for(frequency: f32, bars: usize) -> usize {
+        let position = (frequency / MIN_FREQUENCY).ln() / (MAX_FREQUENCY / MIN_FREQUENCY).ln();
+        (position * bars as f32) as usize
+    }
+
+    #[test]
+    fn levels_every_hop() {
+        let mut analyzer = analyzer(VisualizerStyle::Spectrum);
+        for _ in 1..HOP_SIZE {
+            assert!(analyzer.push(0.0).is_none());
+        }
+        assert!(analyzer.push(0.0).is_some());
+        assert!(analyzer.push(0.0).is_none());
+    }
+
+    #[test]
+    fn silence_is_flat() {
+        let mut analyzer = analyzer(VisualizerStyle::Spectrum);
+        assert!(levels(&mut analyzer, |_| 0.0).iter().all(|level| *level == 0.0));
+    }
+
+    // Below a few hundred Hz the bars are narrower than an FFT bin, so only higher notes land
+    // exactly in the bar their frequency maps to
+    #[test]
+    fn sine_peaks_in_its_bar() {
+        for frequency in [440.0, 1000.0, 8000.0] {
+            let mut analyzer = analyzer(VisualizerStyle::Spectrum);
+            let levels = levels(&mut analyzer, sine(frequency));
+            let loudest = (0..levels.len()).max_by(|a, b| levels[*a].total_cmp(&levels[*b])).unwrap();
+            assert_eq!(loudest, bar_for(frequency, levels.len()), "{} Hz in {:?}", frequency, levels);
+            // A full scale sine is near the top
+            assert!(levels[loudest] > 0.9, "{} Hz only reached {}", frequency, levels[loudest]);
+        }
+    }
+
+    #[test]
+    fn quieter_is_lower() {
+        let mut loud = analyzer(VisualizerStyle::Spectrum);
+        let mut quiet = analyzer(VisualizerStyle::Spectrum);
+        let bar = bar_for(1000.0, 32);
+        let loud = levels(&mut loud, sine(1000.0))[bar];
+        let quiet = levels(&mut quiet, |i| sine(1000.0)(i) * 0.01)[bar];
+        // 40 dB quieter, two thirds of the way down the 60 dB range
+        assert!((loud - quiet - 40.0 / 60.0).abs() < 0.05, "{} vs {}", loud, quiet);
+    }
+
+    #[test]
+    fn waveform_peaks() {
+        let mut analyzer = analyzer(VisualizerStyle::Waveform);
+        // Louder towards the end of the window
+        let levels = levels(&mut analyzer, |i| if i % 2 == 0 { i as f32 / FFT_SIZE as f32 } else { 0.0 });
+        assert_eq!(levels.len(), 32);
+        assert!(levels.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", levels);
+        assert!((levels[31] - 1.0).abs() < 0.01);
+    }
+}
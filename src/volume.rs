@@ -0,0 +1,96 @@
+use std::time::{Duration, Instant};
+use speedy2d::Graphics2D;
+use speedy2d::color::Color;
+use speedy2d::dimen::Vec2;
+use speedy2d::shape::{Rectangle, RoundedRectangle};
+use speedy2d::window::MouseScrollDistance;
+
+// The slider stays up this long after scrolling the volume
+const SHOW_AFTER_SCROLL: Duration = Duration::from_millis(1500);
+// Touchpads scroll in pixels, this many count as one wheel notch
+const PIXELS_PER_STEP: f64 = 40.0;
+
+// Spotify style volume slider that shows up while hovered, dragged or scrolled
+pub struct VolumeSlider {
+    hovered: bool,
+    dragging: bool,
+    scrolled_at: Option<Instant>,
+    // Scrolling that didn't add up to a whole step yet
+    scroll_remainder: f64,
+}
+
+impl VolumeSlider {
+    pub fn new() -> VolumeSlider {
+        VolumeSlider {
+            hovered: false,
+            dragging: false,
+            scrolled_at: None,
+            scroll_remainder: 0.0,
+        }
+    }
+
+    // The area reacting to the mouse, generously larger than the slider itself
+    fn hover_area(rect: &Rectangle, viewport: Vec2) -> Rectangle {
+        let margin = Vec2::new(viewport.x * 0.02, viewport.y * 0.03);
+        Rectangle::new(rect.top_left() - margin, rect.bottom_right() + margin)
+    }
+
+    // Returns the volume to set while dragging
+    pub fn mouse_move(&mut self, rect: &Rectangle, viewport: Vec2, position: Vec2) -> Option<i8> {
+        self.hovered = VolumeSlider::hover_area(rect, viewport).contains(position);
+        match self.dragging {
+            true => Some(volume_at(rect, position.x)),
+            false => None,
+        }
+    }
+
+    // Starts dragging if the press is on the slider, returns the volume to set
+    pub fn mouse_down(&mut self, rect: &Rectangle, viewport: Vec2, position: Vec2) -> Option<i8> {
+        if !VolumeSlider::hover_area(rect, viewport).contains(position) {
+            return None;
+        }
+        self.dragging = true;
+        Some(volume_at(rect, position.x))
+    }
+
+    pub fn mouse_up(&mut self) {
+        self.dragging = false;
+    }
+
+    // Turns wheel or touchpad scrolling into a new volume, None until it adds up to a whole step
+    pub fn scroll(&mut self, distance: MouseScrollDistance, volume: i8, step: u8) -> Option<i8> {
+        if volume < 0 || step == 0 {
+            return None;
+        }
+        self.scroll_remainder += match distance {
+            MouseScrollDistance::Lines { y, .. } => y,
+            MouseScrollDistance::Pixels { y, .. } => y / PIXELS_PER_STEP,
+            MouseScrollDistance::Pages { y, .. } => y * 10.0,
+        };
+        let steps = self.scroll_remainder.trunc();
+        if steps == 0.0 {
+            return None;
+        }
+        self.scroll_remainder -= steps;
+        self.scrolled_at = Some(Instant::now());
+        Some((volume as f64 + steps * step as f64).clamp(0.0, 100.0) as i8)
+    }
+
+    // `volume` is MPD's, -1 when there is no mixer, in which case there's nothing to show
+    pub fn draw(&self, graphics: &mut Graphics2D, rect: &Rectangle, volume: i8, background: Color, foreground: Color, accent: Color) {
+        let scrolled = self.scrolled_at.is_some_and(|at| at.elapsed() < SHOW_AFTER_SCROLL);
+        if volume < 0 || !(self.hovered || self.dragging || scrolled) {
+            return;
+        }
+        let radius = rect.height() / 2.1;
+        graphics.draw_rounded_rectangle(RoundedRectangle::new(*rect.top_left(), *rect.bottom_right(), radius), background);
+        let filled_x = rect.left() + rect.width() * volume as f32 / 100.0;
+        let fill_color = if self.dragging || self.hovered { accent } else { foreground };
+        graphics.draw_rounded_rectangle(RoundedRectangle::from_tuples((rect.left(), rect.top()), (filled_x, rect.bottom()), radius), fill_color);
+        graphics.draw_circle((filled_x, rect.top() + rect.height() / 2.0), rect.height(), foreground);
+    }
+}
+
+fn volume_at(rect: &Rectangle, x: f32) -> i8 {
+    (((x - rect.left()) / rect.width()).clamp(0.0, 1.0) * 100.0).round() as i8
+}
@@ -0,0 +1,242 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use log::{info, warn};
+
+// Editors and file managers tend to write a file in several steps, wait for things to settle
+// before reloading anything
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Change {
+    Config,
+    Assets,
+    Artists,
+}
+
+// What the handler saw, sent over to the thread polling the watcher
+enum Seen {
+    Change(Change),
+    // Something was created or removed on the way to a watched directory
+    Moved,
+}
+
+// A directory to watch. Until it exists its nearest existing parent is watched instead, so it's
+// noticed when it's created (e.g. the first time a config is written).
+struct Target {
+    path: PathBuf,
+    mode: RecursiveMode,
+    // Reported when the directory shows up
+    change: Change,
+    // What's actually watched for it, None if nothing could be
+    watching: Option<PathBuf>,
+}
+
+// Watches the config file, assets and artist images (inotify on Linux) so they can be reloaded
+// while the display keeps running
+pub struct FileWatcher {
+    watcher: RecommendedWatcher,
+    targets: Vec<Target>,
+    receiver: Receiver<Seen>,
+    pending: HashSet<Change>,
+    last_event: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(config_path: &Path, asset_dirs: &[PathBuf], artist_dirs: &[PathBuf]) -> Option<FileWatcher> {
+        let (sender, receiver) = channel();
+        // The config file might not exist yet and is often replaced instead of written to,
+        // so its directory is watched instead of the file itself
+        let config_path = absolute(config_path);
+        let config_dir = config_path.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("/"));
+        let asset_dirs: Vec<PathBuf> = asset_dirs.iter().map(|dir| absolute(dir)).collect();
+        let artist_dirs: Vec<PathBuf> = artist_dirs.iter().map(|dir| absolute(dir)).collect();
+        let mut targets = vec![(config_dir, RecursiveMode::NonRecursive, Change::Config)];
+        targets.extend(asset_dirs.iter().map(|dir| (dir.clone(), RecursiveMode::Recursive, Change::Assets)));
+        targets.extend(artist_dirs.iter().map(|dir| (dir.clone(), RecursiveMode::Recursive, Change::Artists)));
+
+        let classify_config = config_path.clone();
+        let classify_assets = asset_dirs;
+        let classify_artists = artist_dirs;
+        let classify_targets: Vec<PathBuf> = targets.iter().map(|(path, _, _)| path.clone()).collect();
+        let handler = move |result: notify::Result<Event>| {
+            let event = match result {
+                Ok(event) => event,
+                Err(e) => {
+                    warn!("File watcher error: {}", e);
+                    return;
+                },
+            };
+            // Opening and reading files (which we do ourselves when reloading) isn't a change
+            if event.kind.is_access() {
+                return;
+            }
+            for path in &event.paths {
+                if (event.kind.is_create() || event.kind.is_remove()) && classify_targets.iter().any(|target| target.starts_with(path)) {
+                    let _ = sender.send(Seen::Moved);
+                }
+                let change = if path == &classify_config {
+                    Change::Config
+                } else if classify_artists.iter().any(|dir| path.starts_with(dir)) {
+                    Change::Artists
+                } else if classify_assets.iter().any(|dir| path.starts_with(dir)) {
+                    Change::Assets
+                } else {
+                    continue;
+                };
+                let _ = sender.send(Seen::Change(change));
+            }
+        };
+        let watcher = match notify::recommended_watcher(handler) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                warn!("Error creating file watcher, files will not be reloaded: {}", e);
+                return None;
+            },
+        };
+        let mut watcher = FileWatcher {
+            watcher,
+            targets: targets.into_iter()
+                .map(|(path, mode, change)| Target { path, mode, change, watching: None })
+                .collect(),
+            receiver,
+            pending: HashSet::new(),
+            last_event: Instant::now(),
+        };
+        watcher.rearm();
+        Some(watcher)
+    }
+
+    // Called every frame, returns what changed once the files have stopped changing
+    pub fn poll(&mut self) -> Vec<Change> {
+        let mut moved = false;
+        while let Ok(seen) = self.receiver.try_recv() {
+            match seen {
+                Seen::Change(change) => { self.pending.insert(change); },
+                Seen::Moved => moved = true,
+            }
+            self.last_event = Instant::now();
+        }
+        if moved {
+            let appeared = self.rearm();
+            self.pending.extend(appeared);
+        }
+        if self.pending.is_empty() || self.last_event.elapsed() < SETTLE_TIME {
+            return Vec::new();
+        }
+        self.pending.drain().collect()
+    }
+
+    // Watches every target, or its nearest existing parent while it doesn't exist. Returns the
+    // changes for targets that showed up since the last time, whatever is in them is new.
+    fn rearm(&mut self) -> Vec<Change> {
+        let mut appeared = Vec::new();
+        for index in 0..self.targets.len() {
+            if self.arm(index) {
+                appeared.push(self.targets[index].change);
+            }
+        }
+        appeared
+    }
+
+    // Moves the target's watch to where it should be now, returns whether the target itself
+    // showed up (not counting startup, when everything gets loaded anyway)
+    fn arm(&mut self, index: usize) -> bool {
+        let starting = self.targets[index].watching.is_none();
+        // A directory further down can be created before the watch on its parent is in place, so
+        // look again after each one
+        loop {
+            let wanted = nearest_existing(&self.targets[index].path);
+            if wanted == self.targets[index].watching {
+                return false;
+            }
+            // Parents can be shared between targets, or be a target themselves
+            if let Some(old) = self.targets[index].watching.take() {
+                if !self.targets.iter().any(|target| target.watching.as_ref() == Some(&old)) {
+                    let _ = self.watcher.unwatch(&old);
+                }
+            }
+            let path = match wanted {
+                Some(path) => path,
+                None => return false,
+            };
+            let target = &self.targets[index];
+            let exists = path == target.path;
+            let watched = self.targets.iter().any(|other| other.watching.as_ref() == Some(&path));
+            if exists || !watched {
+                let mode = if exists { target.mode } else { RecursiveMode::NonRecursive };
+                match self.watcher.watch(&path, mode) {
+                    Ok(()) if exists => info!("Watching {} for changes", path.display()),
+                    Ok(()) => info!("Watching {} until {} is created", path.display(), target.path.display()),
+                    Err(e) => {
+                        warn!("Error watching {}: {}", path.display(), e);
+                        return false;
+                    },
+                }
+            }
+            self.targets[index].watching = Some(path);
+            if exists {
+                return !starting;
+            }
+        }
+    }
+}
+
+// The path itself if it's a directory, otherwise its closest parent that is
+fn nearest_existing(path: &Path) -> Option<PathBuf> {
+    path.ancestors().find(|dir| dir.is_dir()).map(Path::to_path_buf)
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    match std::fs::canonicalize(path) {
+        Ok(path) => path,
+        // Doesn't exist (yet), fall back to joining it onto the working directory
+        Err(_) => std::env::current_dir().map(|dir| dir.join(path)).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::thread;
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    // Polls like the display does every frame, until something changed or a few seconds passed
+    fn wait_for_changes(watcher: &mut FileWatcher) -> Vec<Change> {
+        let started = Instant::now();
+        loop {
+            let changes = watcher.poll();
+            if !changes.is_empty() || started.elapsed() > Duration::from_secs(5) {
+                return changes;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn notices_the_config_directory_being_created() {
+        let dir = TestDir::new("watcher");
+        let config_path = dir.path().join("config/mpd-display-rs/config.toml");
+        let mut watcher = FileWatcher::new(&config_path, &[], &[]).unwrap();
+        assert!(watcher.poll().is_empty());
+        dir.write("config/mpd-display-rs/config.toml", "");
+        assert_eq!(wait_for_changes(&mut watcher), [Change::Config]);
+        // Watched directly from now on
+        fs::write(&config_path, "[notifications]").unwrap();
+        assert_eq!(wait_for_changes(&mut watcher), [Change::Config]);
+    }
+
+    #[test]
+    fn notices_the_artists_directory_being_created() {
+        let dir = TestDir::new("watcher");
+        let config_path = dir.write("config.toml", "");
+        let mut watcher = FileWatcher::new(&config_path, &[dir.path().join("share/assets")], &[dir.path().join("share/artists")]).unwrap();
+        dir.write("share/artists/Queen.jpg", "");
+        assert_eq!(wait_for_changes(&mut watcher), [Change::Artists]);
+        dir.write("share/artists/ABBA.jpg", "");
+        assert_eq!(wait_for_changes(&mut watcher), [Change::Artists]);
+    }
+}
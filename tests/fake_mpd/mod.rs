@@ -0,0 +1,411 @@
+// A fake MPD server on a local port, speaking just enough of the protocol for the display: status,
+// playlistid, currentsong, idle/noidle, readpicture and pause. Tests script it by editing its
+// state and queueing replies or failures for specific commands.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct FakeSong {
+    pub id: u32,
+    pub file: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+}
+
+// What to do with a command instead of the normal reply
+#[derive(Debug, Clone)]
+pub enum Reply {
+    // Sent as is, should end with "OK\n" or an ACK line
+    Raw(String),
+    // Same, for replies with binary data in them
+    Binary(Vec<u8>),
+    // "ACK [<code>@0] {<command>} <message>"
+    Ack(u32, String),
+    // Closes the connection without replying
+    Disconnect,
+}
+
+pub struct State {
+    pub queue: Vec<FakeSong>,
+    // Position of the current song in the queue
+    pub current: Option<usize>,
+    // "play", "pause" or "stop"
+    pub state: String,
+    pub volume: i8,
+    pub elapsed: f32,
+    pub pictures: HashMap<String, Vec<u8>>,
+    // readpicture sends at most this many bytes per chunk
+    pub chunk_size: usize,
+    // Replies used instead of the normal ones, first in first out per command
+    pub scripted: HashMap<String, VecDeque<Reply>>,
+    // Every command received, with its arguments
+    pub received: Vec<String>,
+    // Subsystems that changed and haven't been reported to idle yet
+    pub changed: Vec<String>,
+    // How many connections have been accepted
+    pub connections: usize,
+    // Needed for everything but ping and close when set
+    pub password: Option<String>,
+    // Only the names, every partition shares the same queue here
+    pub partitions: Vec<String>,
+    // Name and whether it's enabled, the id is the position
+    pub outputs: Vec<(String, bool)>,
+    // Accepts connections but never says anything, like an MPD that hangs
+    pub silent: bool,
+    streams: Vec<TcpStream>,
+    next_id: u32,
+}
+
+pub struct FakeMpd {
+    pub address: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeMpd {
+    pub fn start() -> FakeMpd {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("binding fake MPD");
+        let address = listener.local_addr().unwrap().to_string();
+        let state = Arc::new(Mutex::new(State {
+            queue: Vec::new(),
+            current: None,
+            state: "stop".to_owned(),
+            volume: 50,
+            elapsed: 0.0,
+            pictures: HashMap::new(),
+            chunk_size: 8192,
+            scripted: HashMap::new(),
+            received: Vec::new(),
+            changed: Vec::new(),
+            connections: 0,
+            password: None,
+            partitions: vec!["default".to_owned()],
+            outputs: vec![("Speakers".to_owned(), true), ("Snapcast".to_owned(), false)],
+            silent: false,
+            streams: Vec::new(),
+            next_id: 1,
+        }));
+        let accept_state = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                {
+                    let mut state = accept_state.lock().unwrap();
+                    state.connections += 1;
+                    state.streams.push(stream.try_clone().unwrap());
+                }
+                let connection_state = accept_state.clone();
+                thread::spawn(move || serve(stream, connection_state));
+            }
+        });
+        FakeMpd {
+            address,
+            state,
+        }
+    }
+
+    // Runs `change` on the server state while it's locked
+    pub fn with<T>(&self, change: impl FnOnce(&mut State) -> T) -> T {
+        change(&mut self.state.lock().unwrap())
+    }
+
+    // Adds a song to the queue, returns its id
+    pub fn push(&self, file: &str, title: Option<&str>, artist: Option<&str>) -> u32 {
+        self.with(|state| {
+            let id = state.next_id;
+            state.next_id += 1;
+            state.queue.push(FakeSong {
+                id,
+                file: file.to_owned(),
+                title: title.map(str::to_owned),
+                artist: artist.map(str::to_owned),
+            });
+            state.changed.push("playlist".to_owned());
+            id
+        })
+    }
+
+    pub fn play(&self, position: usize) {
+        self.with(|state| {
+            state.current = Some(position);
+            state.state = "play".to_owned();
+            state.elapsed = 0.0;
+            state.changed.push("player".to_owned());
+        });
+    }
+
+    // The next `command` gets `reply` instead of its normal reply
+    pub fn script(&self, command: &str, reply: Reply) {
+        self.with(|state| state.scripted.entry(command.to_owned()).or_default().push_back(reply));
+    }
+
+    // Drops every open connection, like MPD restarting
+    pub fn kill_connections(&self) {
+        self.with(|state| {
+            for stream in state.streams.drain(..) {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+        });
+    }
+
+    pub fn received(&self) -> Vec<String> {
+        self.with(|state| state.received.clone())
+    }
+
+    pub fn connections(&self) -> usize {
+        self.with(|state| state.connections)
+    }
+}
+
+fn serve(stream: TcpStream, shared: Arc<Mutex<State>>) {
+    let mut writer = stream.try_clone().unwrap();
+    let mut reader = BufReader::new(stream);
+    if shared.lock().unwrap().silent {
+        // Swallows whatever the client sends until it gives up
+        let _ = std::io::copy(&mut reader, &mut std::io::sink());
+        return;
+    }
+    if writer.write_all(b"OK MPD 0.23.5\n").is_err() {
+        return;
+    }
+    // Whether this connection sent the right password
+    let mut authenticated = false;
+    // Commands between command_list_begin and command_list_end, which run together at the end
+    let mut list: Option<Vec<Vec<String>>> = None;
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {},
+        }
+        let arguments = split_arguments(line.trim_end());
+        let commands = match (arguments.first().map(String::as_str), list.as_mut()) {
+            (None, _) => continue,
+            (Some("command_list_begin"), _) => {
+                list = Some(Vec::new());
+                continue;
+            },
+            (Some("command_list_end"), _) => list.take().unwrap_or_default(),
+            (Some(_), Some(list)) => {
+                list.push(arguments);
+                continue;
+            },
+            (Some(_), None) => vec![arguments],
+        };
+        // A list answers with everything before each command's OK, then a single OK, or stops
+        // at the first error
+        let mut bytes = Vec::new();
+        let mut failed = false;
+        for (position, arguments) in commands.iter().enumerate() {
+            let reply = match respond(&shared, &mut reader, arguments, &mut authenticated) {
+                Some(reply) => reply,
+                None => return,
+            };
+            let reply = match reply {
+                Reply::Raw(raw) => raw.into_bytes(),
+                Reply::Binary(reply) => reply,
+                Reply::Ack(code, message) => {
+                    bytes.extend(format!("ACK [{}@{}] {{{}}} {}\n", code, position, arguments[0], message).into_bytes());
+                    failed = true;
+                    break;
+                },
+                Reply::Disconnect => {
+                    let _ = writer.shutdown(Shutdown::Both);
+                    return;
+                },
+            };
+            bytes.extend_from_slice(reply.strip_suffix(b"OK\n").unwrap_or(&reply));
+        }
+        if !failed {
+            bytes.extend_from_slice(b"OK\n");
+        }
+        if writer.write_all(&bytes).is_err() {
+            return;
+        }
+    }
+}
+
+// Reply to one command, None if the client left while idling
+fn respond(shared: &Arc<Mutex<State>>, reader: &mut BufReader<TcpStream>, arguments: &[String], authenticated: &mut bool) -> Option<Reply> {
+    let mut state = shared.lock().unwrap();
+    state.received.push(arguments.join(" "));
+    match state.scripted.get_mut(&arguments[0]).and_then(VecDeque::pop_front) {
+        Some(reply) => Some(reply),
+        None if arguments[0] == "password" => {
+            *authenticated = state.password.as_deref() == arguments.get(1).map(String::as_str);
+            match *authenticated {
+                true => Some(Reply::Raw("OK\n".to_owned())),
+                false => Some(Reply::Ack(3, "incorrect password".to_owned())),
+            }
+        },
+        None if state.password.is_some() && !*authenticated && arguments[0] != "ping" && arguments[0] != "close" => {
+            Some(Reply::Ack(4, format!("you don't have permission for \"{}\"", arguments[0])))
+        },
+        None if arguments[0] == "idle" => {
+            drop(state);
+            wait_idle(reader, shared).map(Reply::Raw)
+        },
+        None => Some(handle(&mut state, arguments)),
+    }
+}
+
+// Blocks until something changed or the client sends noidle, returns None if the client left
+fn wait_idle(reader: &mut BufReader<TcpStream>, state: &Arc<Mutex<State>>) -> Option<String> {
+    reader.get_ref().set_read_timeout(Some(Duration::from_millis(20))).ok()?;
+    let reply = loop {
+        {
+            let mut state = state.lock().unwrap();
+            if !state.changed.is_empty() {
+                let mut reply: String = state.changed.drain(..).map(|subsystem| format!("changed: {}\n", subsystem)).collect();
+                reply.push_str("OK\n");
+                break Some(reply);
+            }
+        }
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) => break None,
+            Ok(_) if line.trim_end() == "noidle" => break Some("OK\n".to_owned()),
+            Ok(_) => {},
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {},
+            Err(_) => break None,
+        }
+    };
+    reader.get_ref().set_read_timeout(None).ok()?;
+    reply
+}
+
+fn handle(state: &mut State, arguments: &[String]) -> Reply {
+    let argument = |i: usize| arguments.get(i).map(String::as_str).unwrap_or("");
+    match argument(0) {
+        "status" => {
+            let mut reply = format!(
+                "volume: {}\nrepeat: 0\nrandom: 0\nsingle: 0\nconsume: 0\nplaylist: 2\nplaylistlength: {}\nstate: {}\n",
+                state.volume, state.queue.len(), state.state,
+            );
+            if let Some(song) = state.current.and_then(|current| state.queue.get(current)) {
+                let position = state.current.unwrap();
+                reply.push_str(&format!("song: {}\nsongid: {}\nelapsed: {:.3}\nduration: 200.000\ntime: {}:200\n", position, song.id, state.elapsed, state.elapsed as u32));
+                if let Some(next) = state.queue.get(position + 1) {
+                    reply.push_str(&format!("nextsong: {}\nnextsongid: {}\n", position + 1, next.id));
+                }
+            }
+            reply.push_str("OK\n");
+            Reply::Raw(reply)
+        },
+        "playlistid" => {
+            let id: u32 = argument(1).parse().unwrap_or(0);
+            match state.queue.iter().position(|song| song.id == id) {
+                Some(position) => Reply::Raw(format!("{}OK\n", song_pairs(&state.queue[position], position))),
+                None => Reply::Ack(50, "No such song".to_owned()),
+            }
+        },
+        "currentsong" => match state.current.and_then(|current| state.queue.get(current).map(|song| (current, song))) {
+            Some((position, song)) => Reply::Raw(format!("{}OK\n", song_pairs(song, position))),
+            None => Reply::Raw("OK\n".to_owned()),
+        },
+        "pause" => {
+            let pause = match argument(1) {
+                "1" => true,
+                "0" => false,
+                _ => state.state == "play",
+            };
+            if state.state != "stop" {
+                state.state = if pause { "pause" } else { "play" }.to_owned();
+                state.changed.push("player".to_owned());
+            }
+            Reply::Raw("OK\n".to_owned())
+        },
+        "readpicture" => {
+            let offset: usize = argument(2).parse().unwrap_or(0);
+            match state.pictures.get(argument(1)) {
+                Some(picture) => {
+                    let chunk = &picture[offset.min(picture.len())..(offset + state.chunk_size).min(picture.len())];
+                    let mut reply = format!("size: {}\ntype: image/png\nbinary: {}\n", picture.len(), chunk.len()).into_bytes();
+                    reply.extend_from_slice(chunk);
+                    reply.extend_from_slice(b"\nOK\n");
+                    Reply::Binary(reply)
+                },
+                None => Reply::Raw("OK\n".to_owned()),
+            }
+        },
+        "listpartitions" => Reply::Raw(state.partitions.iter().map(|name| format!("partition: {}\n", name)).collect::<String>() + "OK\n"),
+        "partition" => match state.partitions.iter().any(|name| name == argument(1)) {
+            true => Reply::Raw("OK\n".to_owned()),
+            false => Reply::Ack(50, "partition does not exist".to_owned()),
+        },
+        "outputs" => {
+            let mut reply: String = state.outputs.iter().enumerate()
+                .map(|(id, (name, enabled))| format!("outputid: {}\noutputname: {}\nplugin: fake\noutputenabled: {}\n", id, name, *enabled as u8))
+                .collect();
+            reply.push_str("OK\n");
+            Reply::Raw(reply)
+        },
+        "enableoutput" | "disableoutput" => {
+            let enabled = argument(0) == "enableoutput";
+            match argument(1).parse().ok().and_then(|id: usize| state.outputs.get_mut(id)) {
+                Some(output) => {
+                    output.1 = enabled;
+                    state.changed.push("output".to_owned());
+                    Reply::Raw("OK\n".to_owned())
+                },
+                None => Reply::Ack(50, "No such audio output".to_owned()),
+            }
+        },
+        "replay_gain_status" => Reply::Raw("replay_gain_mode: off\nOK\n".to_owned()),
+        "ping" => Reply::Raw("OK\n".to_owned()),
+        "close" => Reply::Disconnect,
+        command => Reply::Ack(5, format!("unknown command \"{}\"", command)),
+    }
+}
+
+fn song_pairs(song: &FakeSong, position: usize) -> String {
+    let mut pairs = format!("file: {}\n", song.file);
+    if let Some(title) = &song.title {
+        pairs.push_str(&format!("Title: {}\n", title));
+    }
+    if let Some(artist) = &song.artist {
+        pairs.push_str(&format!("Artist: {}\n", artist));
+    }
+    pairs.push_str(&format!("Time: 200\nduration: 200.000\nPos: {}\nId: {}\n", position, song.id));
+    pairs
+}
+
+// Splits a command line into its words, handling double quoted arguments with backslash escapes
+fn split_arguments(line: &str) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c == ' ' {
+            chars.next();
+            continue;
+        }
+        let mut argument = String::new();
+        if c == '"' {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => argument.extend(chars.next()),
+                    '"' => break,
+                    c => argument.push(c),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c == ' ' {
+                    break;
+                }
+                argument.push(c);
+                chars.next();
+            }
+        }
+        arguments.push(argument);
+    }
+    arguments
+}
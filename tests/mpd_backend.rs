@@ -0,0 +1,332 @@
+// The real MPD connection against a fake server: refreshing, album art, commands, and recovering
+// when the connection goes away
+
+mod fake_mpd;
+
+use std::io;
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+use mpd::Client;
+use mpd::error::{Error, ErrorCode};
+use mpd::idle::{Idle, Subsystem};
+use mpd::status::State;
+use mpd_display_rs::player::{ChangeWatcher, MpdBackend, NowPlaying, PlayerBackend};
+use fake_mpd::{FakeMpd, Reply};
+
+fn playing_server() -> FakeMpd {
+    let server = FakeMpd::start();
+    server.push("first.flac", Some("First"), Some("Artist"));
+    server.push("second.flac", Some("Second"), Some("Other artist"));
+    server.play(0);
+    server
+}
+
+#[test]
+fn refreshes_from_the_server() {
+    let server = playing_server();
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    let mut now_playing = NowPlaying::default();
+    assert!(now_playing.refresh(&mut backend).unwrap());
+    assert_eq!(now_playing.status.state, State::Play);
+    assert_eq!(now_playing.title_and_artist(), ("First".to_owned(), "Artist".to_owned()));
+    assert_eq!(now_playing.up_next_text(), "Other artist - Second");
+    assert!(!now_playing.refresh(&mut backend).unwrap());
+
+    server.play(1);
+    assert!(now_playing.refresh(&mut backend).unwrap());
+    assert_eq!(now_playing.title_and_artist(), ("Second".to_owned(), "Other artist".to_owned()));
+    assert_eq!(now_playing.up_next_text(), "Nothing");
+}
+
+#[test]
+fn reads_pictures_in_chunks() {
+    let server = playing_server();
+    let picture: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+    server.with(|state| {
+        state.pictures.insert("first.flac".to_owned(), picture.clone());
+        state.chunk_size = 4096;
+    });
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    assert_eq!(backend.read_picture("first.flac").unwrap(), Some(picture));
+    let requests: Vec<String> = server.received().into_iter().filter(|command| command.starts_with("readpicture")).collect();
+    assert_eq!(requests, ["readpicture first.flac 0", "readpicture first.flac 4096", "readpicture first.flac 8192"]);
+
+    // The connection is still usable afterwards
+    assert_eq!(backend.status().unwrap().queue_len, 2);
+}
+
+#[test]
+fn missing_pictures() {
+    let server = playing_server();
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    assert_eq!(backend.read_picture("second.flac").unwrap(), None);
+
+    server.script("readpicture", Reply::Ack(50, "No such file".to_owned()));
+    assert!(matches!(backend.read_picture("gone.flac"), Err(Error::Server(_))));
+    // An ACK doesn't cost the connection
+    assert!(backend.status().is_ok());
+    assert_eq!(server.connections(), 1);
+}
+
+#[test]
+fn file_names_are_quoted() {
+    let server = playing_server();
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    backend.read_picture("Some \"quoted\" name.flac").unwrap();
+    assert!(server.received().contains(&"readpicture Some \"quoted\" name.flac 0".to_owned()));
+}
+
+#[test]
+fn reconnects_after_losing_the_connection() {
+    let server = playing_server();
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    let mut now_playing = NowPlaying::default();
+    now_playing.refresh(&mut backend).unwrap();
+
+    server.kill_connections();
+    assert!(now_playing.refresh(&mut backend).is_err());
+    // What was playing is kept while disconnected
+    assert_eq!(now_playing.title_and_artist(), ("First".to_owned(), "Artist".to_owned()));
+
+    // The first attempt after losing the connection doesn't wait
+    now_playing.refresh(&mut backend).unwrap();
+    assert_eq!(server.connections(), 2);
+    assert_eq!(now_playing.status.state, State::Play);
+}
+
+#[test]
+fn broken_replies_drop_the_connection() {
+    let server = playing_server();
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    server.script("status", Reply::Disconnect);
+    // The mpd crate takes a reply cut short by the connection closing for an empty one, so it's
+    // the command after it that fails
+    let _ = backend.status();
+    assert!(backend.status().is_err());
+    assert!(backend.status().is_ok());
+    assert_eq!(server.connections(), 2);
+}
+
+#[test]
+fn gives_up_on_a_server_that_never_answers() {
+    let server = FakeMpd::start();
+    server.with(|state| state.silent = true);
+    let started = Instant::now();
+    let error = MpdBackend::connect(&server.address, None).err().unwrap();
+    assert!(matches!(error, Error::Io(e) if e.kind() == io::ErrorKind::TimedOut));
+    assert!(started.elapsed() < Duration::from_secs(10));
+
+    // Same when reconnecting to a server that has hung since
+    let server = playing_server();
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    server.with(|state| state.silent = true);
+    server.kill_connections();
+    let _ = backend.status();
+    let started = Instant::now();
+    assert!(matches!(backend.status(), Err(Error::Io(e)) if e.kind() == io::ErrorKind::TimedOut));
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn server_errors_keep_the_connection() {
+    let server = playing_server();
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    server.script("status", Reply::Ack(4, "you don't have permission for \"status\"".to_owned()));
+    assert!(matches!(backend.status(), Err(Error::Server(_))));
+    assert!(backend.status().is_ok());
+    assert_eq!(server.connections(), 1);
+}
+
+#[test]
+fn toggles_pause() {
+    let server = playing_server();
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    backend.toggle_pause().unwrap();
+    assert_eq!(backend.status().unwrap().state, State::Pause);
+    backend.toggle_pause().unwrap();
+    assert_eq!(backend.status().unwrap().state, State::Play);
+    assert_eq!(server.received().iter().filter(|command| *command == "pause").count(), 2);
+}
+
+#[test]
+fn idle_reports_changes() {
+    let server = playing_server();
+    server.with(|state| state.changed.clear());
+    let mut client = Client::new(TcpStream::connect(&server.address).unwrap()).unwrap();
+    let idle = client.idle(&[]).unwrap();
+    server.push("third.flac", None, None);
+    assert_eq!(idle.get().unwrap(), [Subsystem::Queue]);
+}
+
+fn locked_server() -> FakeMpd {
+    let server = playing_server();
+    server.with(|state| state.password = Some("secret".to_owned()));
+    server
+}
+
+fn server_error_code(error: Error) -> Option<ErrorCode> {
+    match error {
+        Error::Server(e) => Some(e.code),
+        _ => None,
+    }
+}
+
+#[test]
+fn logs_in_with_the_password() {
+    let server = locked_server();
+    let mut backend = MpdBackend::connect(&server.address, Some("secret")).unwrap();
+    let mut now_playing = NowPlaying::default();
+    assert!(now_playing.refresh(&mut backend).unwrap());
+    assert_eq!(now_playing.status.state, State::Play);
+}
+
+#[test]
+fn wrong_password() {
+    let server = locked_server();
+    let error = MpdBackend::connect(&server.address, Some("guess")).err().unwrap();
+    assert_eq!(server_error_code(error), Some(ErrorCode::Password));
+}
+
+#[test]
+fn missing_password() {
+    let server = locked_server();
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    assert_eq!(server_error_code(backend.status().unwrap_err()), Some(ErrorCode::Permission));
+}
+
+#[test]
+fn logs_in_again_after_reconnecting() {
+    let server = locked_server();
+    let mut backend = MpdBackend::connect(&server.address, Some("secret")).unwrap();
+    assert!(backend.status().is_ok());
+    server.kill_connections();
+    // The first command after losing the connection may still fail, see broken_replies_drop_the_connection
+    let _ = backend.status();
+    assert!(backend.status().is_ok());
+    let logins = server.received().iter().filter(|command| command.starts_with("password")).count();
+    assert_eq!(logins, 2);
+}
+
+fn partitioned_server() -> FakeMpd {
+    let server = playing_server();
+    server.with(|state| state.partitions.push("kitchen".to_owned()));
+    server
+}
+
+#[test]
+fn lists_partitions() {
+    let server = partitioned_server();
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    assert_eq!(backend.partitions().unwrap(), vec!["default".to_owned(), "kitchen".to_owned()]);
+}
+
+#[test]
+fn switches_partition_again_after_reconnecting() {
+    let server = partitioned_server();
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    backend.switch_partition("kitchen").unwrap();
+    server.kill_connections();
+    // The first command after losing the connection may still fail, see broken_replies_drop_the_connection
+    let _ = backend.status();
+    assert!(backend.status().is_ok());
+    let switches = server.received().iter().filter(|command| command.as_str() == "partition kitchen").count();
+    assert_eq!(switches, 2);
+}
+
+#[test]
+fn unknown_partition() {
+    let server = partitioned_server();
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    backend.switch_partition("kitchen").unwrap();
+    assert_eq!(server_error_code(backend.switch_partition("garage").unwrap_err()), Some(ErrorCode::NoExist));
+    // Still in the kitchen after reconnecting
+    server.kill_connections();
+    let _ = backend.status();
+    assert!(backend.status().is_ok());
+    let kitchen = server.received().iter().filter(|command| command.as_str() == "partition kitchen").count();
+    assert_eq!(kitchen, 2);
+}
+
+#[test]
+fn toggles_outputs() {
+    let server = playing_server();
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    let outputs = backend.outputs().unwrap();
+    assert_eq!(outputs.iter().map(|output| (output.name.as_str(), output.enabled)).collect::<Vec<_>>(), [("Speakers", true), ("Snapcast", false)]);
+    backend.set_output(1, true).unwrap();
+    backend.set_output(0, false).unwrap();
+    assert_eq!(server.with(|state| state.outputs.clone()), [("Speakers".to_owned(), false), ("Snapcast".to_owned(), true)]);
+    assert_eq!(server_error_code(backend.set_output(7, true).unwrap_err()), Some(ErrorCode::NoExist));
+}
+
+// Polls until the watcher reports something, or gives up after a few seconds
+fn wait_for_changes(watcher: &ChangeWatcher) -> Vec<Subsystem> {
+    let started = Instant::now();
+    loop {
+        let changes = watcher.poll();
+        if !changes.is_empty() || started.elapsed() > Duration::from_secs(5) {
+            return changes;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn wait_for_connections(server: &FakeMpd, connections: usize) {
+    let started = Instant::now();
+    while server.connections() < connections && started.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn watches_for_changes() {
+    let server = playing_server();
+    server.with(|state| state.changed.clear());
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    let watcher = backend.watch(&[Subsystem::Output]).unwrap();
+    // The watcher connects on its own thread, changes before that aren't seen
+    wait_for_connections(&server, 2);
+    assert!(watcher.poll().is_empty());
+    backend.set_output(1, true).unwrap();
+    assert_eq!(wait_for_changes(&watcher), [Subsystem::Output]);
+}
+
+#[test]
+fn watcher_reconnects() {
+    let server = playing_server();
+    server.with(|state| state.changed.clear());
+    let mut backend = MpdBackend::connect(&server.address, None).unwrap();
+    let watcher = backend.watch(&[Subsystem::Output]).unwrap();
+    wait_for_connections(&server, 2);
+
+    server.kill_connections();
+    // Anything could have changed while it was disconnected
+    assert_eq!(wait_for_changes(&watcher), [Subsystem::Output]);
+    // Only the watcher has reconnected so far
+    assert_eq!(server.connections(), 3);
+
+    let _ = backend.status();
+    backend.set_output(1, true).unwrap();
+    assert_eq!(wait_for_changes(&watcher), [Subsystem::Output]);
+}
+
+#[test]
+fn watcher_keeps_trying_while_the_server_is_down() {
+    let server = playing_server();
+    server.with(|state| {
+        state.changed.clear();
+        state.silent = true;
+    });
+    let mut backend = MpdBackend::new(&server.address, None);
+    // Connecting happens in the background, so watching a hung server doesn't block
+    let started = Instant::now();
+    let watcher = backend.watch(&[Subsystem::Output]).unwrap();
+    assert!(started.elapsed() < Duration::from_millis(500));
+    assert!(watcher.poll().is_empty());
+
+    wait_for_connections(&server, 1);
+    server.with(|state| state.silent = false);
+    server.kill_connections();
+    assert_eq!(wait_for_changes(&watcher), [Subsystem::Output]);
+}